@@ -1,4 +1,5 @@
 use super::Als;
+use crate::power;
 use std::sync::mpsc::Sender;
 use std::thread;
 use std::time::Duration;
@@ -8,11 +9,16 @@ const WAITING_SLEEP_MS: u64 = 100;
 pub struct Controller {
     als: Box<dyn Als>,
     value_txs: Vec<Sender<String>>,
+    power: power::Listener,
 }
 
 impl Controller {
-    pub fn new(als: Box<dyn Als>, value_txs: Vec<Sender<String>>) -> Self {
-        Self { als, value_txs }
+    pub fn new(als: Box<dyn Als>, value_txs: Vec<Sender<String>>, power: power::Listener) -> Self {
+        Self {
+            als,
+            value_txs,
+            power,
+        }
     }
 
     pub fn run(&mut self) {
@@ -32,6 +38,6 @@ impl Controller {
             Err(err) => log::error!("Unable to get ALS value: {:?}", err),
         };
 
-        thread::sleep(Duration::from_millis(WAITING_SLEEP_MS));
+        thread::sleep(Duration::from_millis(WAITING_SLEEP_MS) * self.power.state().slowdown);
     }
 }
@@ -22,6 +22,26 @@ fn find_profile(raw: u64, thresholds: &HashMap<u64, String>) -> String {
         .unwrap_or_else(|| panic!("Unable to find ALS profile for value '{}'", raw))
 }
 
+/// Profiles of the configured ALS, ordered from the darkest to the brightest conditions.
+pub fn configured_profiles(als: &crate::config::Als) -> Vec<String> {
+    match als {
+        crate::config::Als::Iio { thresholds, .. }
+        | crate::config::Als::Time { thresholds }
+        | crate::config::Als::Webcam { thresholds, .. } => ordered_profiles(thresholds),
+        crate::config::Als::None => vec!["none".to_string()],
+    }
+}
+
+/// Profiles ordered from the darkest to the brightest conditions, each one listed only once.
+pub fn ordered_profiles(thresholds: &HashMap<u64, String>) -> Vec<String> {
+    thresholds
+        .iter()
+        .sorted_by_key(|(lux, _)| *lux)
+        .map(|(_, profile)| profile.to_string())
+        .unique()
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,6 +85,19 @@ mod tests {
         assert_eq!("dark", find_profile(9, &thresholds));
     }
 
+    #[test]
+    fn test_ordered_profiles() {
+        let thresholds = vec![(0, "night"), (7, "dark"), (13, "bright"), (18, "dark")]
+            .into_iter()
+            .map(|(lux, profile)| (lux, profile.to_string()))
+            .collect();
+
+        assert_eq!(
+            vec!["night", "dark", "bright"],
+            ordered_profiles(&thresholds)
+        );
+    }
+
     #[test]
     #[should_panic]
     fn test_find_profile_panics_on_empty_thresholds() {
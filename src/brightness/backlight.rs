@@ -1,12 +1,23 @@
 use crate::device_file::{read, write};
+use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
 use dbus::channel::Sender;
 use dbus::{self, blocking::Connection, Message};
-use inotify::{Inotify, WatchMask};
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+use itertools::Itertools;
 use std::error::Error;
 use std::fs;
 use std::fs::File;
 use std::io::ErrorKind;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const BACKLIGHT_CLASS_PATH: &str = "/sys/class/backlight";
+pub const LEDS_CLASS_PATH: &str = "/sys/class/leds";
+
+const DBUS_TIMEOUT_MS: u64 = 1000;
+const LOGIND_DESTINATION: &str = "org.freedesktop.login1";
+const LOGIND_SESSION: &str = "/org/freedesktop/login1/session/auto";
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
 
 struct Dbus {
     connection: Connection,
@@ -21,7 +32,9 @@ pub struct Backlight {
     current: Option<u64>,
     dbus: Option<Dbus>,
     has_write_permission: bool,
-    pending_dbus_write: bool,
+    pending_dbus_write: Option<(u64, Instant)>,
+    hw_changed_watch: Option<WatchDescriptor>,
+    hardware_changed: bool,
 }
 
 impl Backlight {
@@ -43,29 +56,18 @@ impl Backlight {
         } else {
             let file = File::open(&brightness_path)?;
 
-            let id = Path::new(path)
-                .file_name()
-                .and_then(|x| x.to_str())
-                .ok_or("Unable to identify backlight ID")?;
-
-            let message = Message::new_method_call(
-                "org.freedesktop.login1",
-                "/org/freedesktop/login1/session/auto",
-                "org.freedesktop.login1.Session",
-                "SetBrightness",
-            )
-            .ok()
-            .map(|m| m.append2("backlight", id));
-
-            let connection = Connection::new_system().ok().and_then(|connection| {
-                message.map(|message| Dbus {
-                    connection,
-                    message,
-                })
-            });
+            let dbus = logind(path).map_err(|err| {
+                format!(
+                    "No permission to write to {} and unable to use logind instead ({}). \
+                     Install the udev rule and add your user to the 'video' group, \
+                     or run wluma in a session managed by systemd-logind or elogind",
+                    brightness_path.display(),
+                    err
+                )
+            })?;
 
             log::debug!("Using DBUS for {} to change brightness value", path);
-            (file, connection)
+            (file, Some(dbus))
         };
 
         let max_brightness = fs::read_to_string(Path::new(path).join("max_brightness"))?
@@ -75,12 +77,17 @@ impl Backlight {
         let inotify = Inotify::init()?;
         inotify.watches().add(&brightness_path, WatchMask::MODIFY)?;
 
+        // Kernel reports here the changes made by hardware itself (e.g. brightness keys)
         let brightness_hw_changed_path = Path::new(path).join("brightness_hw_changed");
-        if Path::new(&brightness_hw_changed_path).exists() {
-            inotify
-                .watches()
-                .add(&brightness_hw_changed_path, WatchMask::MODIFY)?;
-        }
+        let hw_changed_watch = if Path::new(&brightness_hw_changed_path).exists() {
+            Some(
+                inotify
+                    .watches()
+                    .add(&brightness_hw_changed_path, WatchMask::MODIFY)?,
+            )
+        } else {
+            None
+        };
 
         Ok(Self {
             file,
@@ -90,9 +97,97 @@ impl Backlight {
             current: None,
             dbus,
             has_write_permission,
-            pending_dbus_write: false,
+            pending_dbus_write: None,
+            hw_changed_watch,
+            hardware_changed: false,
         })
     }
+
+    pub fn min_brightness(&self) -> u64 {
+        self.min_brightness
+    }
+
+    /// Writes via logind are asynchronous, until the file reflects the value that we asked for,
+    /// any changes of the file are considered to be caused by us rather than by user.
+    fn verify_dbus_write(
+        &mut self,
+        (expected, sent_at): (u64, Instant),
+        cached: u64,
+    ) -> Result<u64, Box<dyn Error>> {
+        let value = read(&mut self.file)? as u64;
+
+        if value == expected {
+            self.pending_dbus_write = None;
+            Ok(cached)
+        } else if sent_at.elapsed() > Duration::from_millis(DBUS_TIMEOUT_MS) {
+            log::warn!(
+                "Brightness change via logind to {} was not applied, current value is {}",
+                expected,
+                value
+            );
+            self.pending_dbus_write = None;
+            self.current = Some(value);
+            Ok(value)
+        } else {
+            Ok(cached)
+        }
+    }
+}
+
+/// Prepares the logind call to change brightness of the device. It can't be checked without
+/// changing brightness, so it's only checked that wluma runs in a session managed by logind.
+fn logind(path: &str) -> Result<Dbus, Box<dyn Error>> {
+    let (subsystem, id) = logind_device(&fs::canonicalize(path)?)?;
+
+    let message = Message::new_method_call(
+        LOGIND_DESTINATION,
+        LOGIND_SESSION,
+        SESSION_INTERFACE,
+        "SetBrightness",
+    )?
+    .append2(subsystem, id);
+
+    let connection = Connection::new_system()?;
+
+    let active: bool = connection
+        .with_proxy(
+            LOGIND_DESTINATION,
+            LOGIND_SESSION,
+            Duration::from_millis(DBUS_TIMEOUT_MS),
+        )
+        .get(SESSION_INTERFACE, "Active")?;
+    if !active {
+        log::warn!("Session is not active, logind might refuse to change brightness");
+    }
+
+    Ok(Dbus {
+        connection,
+        message,
+    })
+}
+
+/// Identifies the subsystem (`backlight` or `leds`) and the name of the device,
+/// as expected by logind.
+fn logind_device(path: &Path) -> Result<(&'static str, String), Box<dyn Error>> {
+    let id = path
+        .file_name()
+        .and_then(|x| x.to_str())
+        .ok_or("Unable to identify backlight ID")?;
+
+    let subsystem = match path
+        .parent()
+        .and_then(|parent| parent.file_name())
+        .and_then(|x| x.to_str())
+    {
+        Some("backlight") => "backlight",
+        Some("leds") => "leds",
+        _ => Err(format!(
+            "Unable to identify subsystem of {}, expected a 'backlight' or 'leds' device",
+            path.display()
+        ))?,
+    };
+
+    Ok((subsystem, id.to_string()))
 }
 
 impl super::Brightness for Backlight {
@@ -106,15 +201,26 @@ impl super::Brightness for Backlight {
         let mut buffer = [0u8; 1024];
         match (self.inotify.read_events(&mut buffer), self.current) {
             (_, None) => update(self),
-            (Ok(mut events), Some(cached)) => {
-                if self.pending_dbus_write || events.next().is_none() {
-                    self.pending_dbus_write = false;
-                    Ok(cached)
-                } else {
-                    update(self)
+            (Ok(events), Some(cached)) => {
+                let watches = events.map(|event| event.wd).collect_vec();
+                self.hardware_changed = self
+                    .hw_changed_watch
+                    .as_ref()
+                    .is_some_and(|wd| watches.contains(wd));
+
+                match (self.pending_dbus_write, watches.is_empty()) {
+                    (None, true) => Ok(cached),
+                    (None, false) => update(self),
+                    (Some(pending), _) => self.verify_dbus_write(pending, cached),
+                }
+            }
+            (Err(err), Some(cached)) if err.kind() == ErrorKind::WouldBlock => {
+                self.hardware_changed = false;
+                match self.pending_dbus_write {
+                    Some(pending) => self.verify_dbus_write(pending, cached),
+                    None => Ok(cached),
                 }
             }
-            (Err(err), Some(cached)) if err.kind() == ErrorKind::WouldBlock => Ok(cached),
             (Err(err), _) => Err(err.into()),
         }
     }
@@ -128,7 +234,7 @@ impl super::Brightness for Backlight {
             dbus.connection
                 .send(dbus.message.duplicate()?.append1(value as u32))
                 .map_err(|_| "Unable to send brightness change message via dbus")?;
-            self.pending_dbus_write = true;
+            self.pending_dbus_write = Some((value, Instant::now()));
         } else {
             Err(std::io::Error::from(ErrorKind::PermissionDenied))?
         }
@@ -143,4 +249,138 @@ impl super::Brightness for Backlight {
             _ => Ok(value),
         }
     }
+
+    fn max_brightness(&self) -> u64 {
+        self.max_brightness
+    }
+
+    fn hardware_changed(&mut self) -> bool {
+        self.hardware_changed
+    }
+}
+
+/// Finds the most appropriate backlight device, preferring firmware, then platform,
+/// then raw interfaces (as recommended by the kernel documentation).
+pub fn find_backlight(class_path: &Path) -> Option<PathBuf> {
+    class_path
+        .read_dir()
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let priority = match fs::read_to_string(e.path().join("type"))
+                .unwrap_or_default()
+                .trim()
+            {
+                "firmware" => 0,
+                "platform" => 1,
+                "raw" => 2,
+                _ => return None,
+            };
+            Some((priority, e.path()))
+        })
+        .sorted()
+        .map(|(_, path)| path)
+        .next()
+}
+
+/// Finds the keyboard backlight LED device.
+pub fn find_keyboard_backlight(class_path: &Path) -> Option<PathBuf> {
+    class_path
+        .read_dir()
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with("::kbd_backlight"))
+        })
+        .sorted()
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    fn device(root: &Path, name: &str, kind: Option<&str>) {
+        let path = root.join(name);
+        fs::create_dir_all(&path).unwrap();
+        if let Some(kind) = kind {
+            fs::write(path.join("type"), format!("{}\n", kind)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_find_backlight_prefers_firmware_then_platform_then_raw() {
+        let sysfs = TempDir::new("backlight-priority");
+        device(&sysfs, "intel_backlight", Some("raw"));
+        device(&sysfs, "dell_backlight", Some("platform"));
+        device(&sysfs, "acpi_video0", Some("firmware"));
+
+        assert_eq!(Some(sysfs.join("acpi_video0")), find_backlight(&sysfs));
+
+        fs::remove_dir_all(sysfs.join("acpi_video0")).unwrap();
+        assert_eq!(Some(sysfs.join("dell_backlight")), find_backlight(&sysfs));
+
+        fs::remove_dir_all(sysfs.join("dell_backlight")).unwrap();
+        assert_eq!(Some(sysfs.join("intel_backlight")), find_backlight(&sysfs));
+    }
+
+    #[test]
+    fn test_find_backlight_ignores_devices_of_unknown_type() {
+        let sysfs = TempDir::new("backlight-unknown");
+        device(&sysfs, "weird", Some("unknown"));
+        device(&sysfs, "broken", None);
+
+        assert_eq!(None, find_backlight(&sysfs));
+    }
+
+    #[test]
+    fn test_find_backlight_missing_class() {
+        let sysfs = TempDir::new("backlight-missing");
+
+        assert_eq!(None, find_backlight(&sysfs.join("backlight")));
+    }
+
+    #[test]
+    fn test_logind_device() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            ("backlight", "intel_backlight".to_string()),
+            logind_device(Path::new(
+                "/sys/devices/pci0000:00/0000:00:02.0/drm/card1/card1-eDP-1/backlight/intel_backlight"
+            ))?
+        );
+        assert_eq!(
+            ("leds", "dell::kbd_backlight".to_string()),
+            logind_device(Path::new(
+                "/sys/devices/platform/dell-laptop/leds/dell::kbd_backlight"
+            ))?
+        );
+        assert!(logind_device(Path::new("/sys/devices/platform/dell-laptop")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_keyboard_backlight() {
+        let sysfs = TempDir::new("leds");
+        device(&sysfs, "input3::capslock", None);
+        device(&sysfs, "phy0-led", None);
+        device(&sysfs, "tpacpi::kbd_backlight", None);
+
+        assert_eq!(
+            Some(sysfs.join("tpacpi::kbd_backlight")),
+            find_keyboard_backlight(&sysfs)
+        );
+    }
+
+    #[test]
+    fn test_find_keyboard_backlight_none() {
+        let sysfs = TempDir::new("leds-none");
+        device(&sysfs, "input3::capslock", None);
+
+        assert_eq!(None, find_keyboard_backlight(&sysfs));
+    }
 }
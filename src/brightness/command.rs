@@ -0,0 +1,275 @@
+use super::RateLimited;
+use std::error::Error;
+use std::io::Read;
+use std::process::{self, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const WAIT_STEP_MS: u64 = 5;
+
+/// Controls brightness through external commands, e.g. vendor tools or helpers.
+///
+/// The `set` command template may contain `{value}`, which is replaced with the desired brightness.
+/// The `get` command is expected to print the current brightness to stdout.
+pub struct Command {
+    get: String,
+    set: String,
+    min_brightness: u64,
+    max_brightness: u64,
+    timeout: Duration,
+    poll_interval: Duration,
+    current: Option<u64>,
+    last_poll: Instant,
+    limit: RateLimited,
+}
+
+impl Command {
+    pub fn new(
+        get: &str,
+        set: &str,
+        min_brightness: u64,
+        max_brightness: u64,
+        timeout: Duration,
+        poll_interval: Duration,
+        set_interval: Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut command = Self {
+            get: get.to_string(),
+            set: set.to_string(),
+            min_brightness,
+            max_brightness,
+            timeout,
+            poll_interval,
+            current: None,
+            last_poll: Instant::now(),
+            limit: RateLimited::new(set_interval),
+        };
+
+        // Fail early if the helper is not usable
+        command.read()?;
+
+        Ok(command)
+    }
+
+    fn run(&self, command: &str) -> Result<String, Box<dyn Error>> {
+        let mut child = process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Pipes are read while waiting, a command with a lot of output would block otherwise
+        let stdout = drain(child.stdout.take());
+        let stderr = drain(child.stderr.take());
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(format!(
+                    "Command '{}' timed out after {:?}",
+                    command, self.timeout
+                ))?;
+            }
+            thread::sleep(Duration::from_millis(WAIT_STEP_MS));
+        };
+
+        let stdout = stdout.join().map_err(|_| "Unable to read command output")?;
+        let stderr = stderr.join().map_err(|_| "Unable to read command output")?;
+        if !status.success() {
+            Err(format!(
+                "Command '{}' failed ({}): {}",
+                command,
+                status,
+                String::from_utf8_lossy(&stderr).trim()
+            ))?;
+        }
+
+        Ok(String::from_utf8_lossy(&stdout).trim().to_string())
+    }
+
+    fn read(&mut self) -> Result<u64, Box<dyn Error>> {
+        let output = self.run(&self.get)?;
+        let value = output
+            .parse::<f64>()
+            .map_err(|_| format!("Command '{}' returned '{}'", self.get, output))?
+            .round() as u64;
+
+        self.current = Some(value);
+        self.last_poll = Instant::now();
+        Ok(value)
+    }
+
+    fn write(&mut self, value: u64) -> Result<(), Box<dyn Error>> {
+        self.run(&self.set.replace("{value}", &value.to_string()))?;
+
+        self.current = Some(value);
+        self.last_poll = Instant::now();
+        self.limit.written();
+        Ok(())
+    }
+}
+
+fn drain(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+impl super::Brightness for Command {
+    fn get(&mut self) -> Result<u64, Box<dyn Error>> {
+        match self.limit.pending() {
+            Some((pending, false)) => return Ok(pending),
+            Some((pending, true)) => self.write(pending)?,
+            None => {}
+        }
+
+        // Spawning a process on every frame would be too expensive, so the helper is only run to
+        // read the value every `poll_interval_ms`, just as it only writes every `set_interval_ms`
+        match self.current {
+            Some(cached) if self.last_poll.elapsed() < self.poll_interval => Ok(cached),
+            _ => self.read(),
+        }
+    }
+
+    fn set(&mut self, value: u64) -> Result<u64, Box<dyn Error>> {
+        let value = value.clamp(self.min_brightness, self.max_brightness);
+
+        if self.limit.allows(value) {
+            self.write(value)?;
+        }
+
+        Ok(value)
+    }
+
+    fn max_brightness(&self) -> u64 {
+        self.max_brightness
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Brightness;
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use std::fs;
+    use std::path::Path;
+
+    fn setup(name: &str, set_interval_ms: u64) -> Result<(Command, TempDir), Box<dyn Error>> {
+        let dir = TempDir::new(&format!("command-{}", name));
+        let path = dir.join("brightness");
+        fs::write(&path, "50\n")?;
+
+        let command = Command::new(
+            &format!("cat '{}'", path.display()),
+            &format!("echo {{value}} > '{}'", path.display()),
+            1,
+            100,
+            Duration::from_secs(5),
+            Duration::from_secs(60),
+            Duration::from_millis(set_interval_ms),
+        )?;
+        Ok((command, dir))
+    }
+
+    fn stored(dir: &Path) -> Result<String, Box<dyn Error>> {
+        Ok(fs::read_to_string(dir.join("brightness"))?
+            .trim()
+            .to_string())
+    }
+
+    #[test]
+    fn test_get_and_set() -> Result<(), Box<dyn Error>> {
+        let (mut command, dir) = setup("get-set", 0)?;
+
+        assert_eq!(50, command.get()?);
+        assert_eq!(70, command.set(70)?);
+        assert_eq!("70", stored(&dir)?);
+        assert_eq!(70, command.get()?);
+
+        // Values are clamped to the configured range
+        assert_eq!(100, command.set(150)?);
+        assert_eq!(1, command.set(0)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_is_rate_limited() -> Result<(), Box<dyn Error>> {
+        let (mut command, dir) = setup("rate-limit", 60_000)?;
+
+        command.set(60)?;
+        command.set(61)?;
+        command.set(62)?;
+
+        // Only the first value was written, the last one is pending
+        assert_eq!("60", stored(&dir)?);
+        assert_eq!(62, command.get()?);
+
+        command.limit.interval = Duration::ZERO;
+        assert_eq!(62, command.get()?);
+        assert_eq!("62", stored(&dir)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut command = Command {
+            get: "sleep 5".to_string(),
+            set: String::new(),
+            min_brightness: 0,
+            max_brightness: 100,
+            timeout: Duration::from_millis(50),
+            poll_interval: Duration::ZERO,
+            current: None,
+            last_poll: Instant::now(),
+            limit: RateLimited::new(Duration::ZERO),
+        };
+
+        let err = command.get().unwrap_err().to_string();
+        assert!(err.contains("timed out"), "{}", err);
+    }
+
+    #[test]
+    fn test_command_with_large_output() -> Result<(), Box<dyn Error>> {
+        // More output than fits into a pipe buffer
+        let mut command = Command::new(
+            "head -c 100000 /dev/zero | tr '\\0' ' '; echo 42",
+            "true",
+            0,
+            100,
+            Duration::from_secs(5),
+            Duration::ZERO,
+            Duration::ZERO,
+        )?;
+
+        assert_eq!(42, command.get()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failing_command() {
+        let result = Command::new(
+            "echo nope; exit 3",
+            "true",
+            0,
+            100,
+            Duration::from_secs(5),
+            Duration::ZERO,
+            Duration::ZERO,
+        );
+
+        assert!(result.is_err());
+    }
+}
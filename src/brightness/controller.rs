@@ -1,7 +1,8 @@
-use super::Brightness;
+use super::{Brightness, Curve};
+use crate::config::Ignore;
 use std::sync::mpsc::{Receiver, Sender};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 const TRANSITION_MAX_MS: u64 = 200;
 const TRANSITION_STEP_MS: u64 = 1;
@@ -11,8 +12,76 @@ pub struct Controller {
     brightness: Box<dyn Brightness>,
     user_tx: Sender<u64>,
     prediction_rx: Receiver<u64>,
+    event_rx: Receiver<Event>,
+    idle_dim: u64,
     current: Option<u64>,
     target: Option<Target>,
+    restore: Option<u64>,
+    followers: Vec<Follower>,
+    ignore: Ignore,
+    external: Option<External>,
+    brightness_cap: Option<u64>,
+    hold: Option<Instant>,
+    disabled: bool,
+    session_inactive: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Event {
+    /// User became idle (`true`) or active again (`false`)
+    Idle(bool),
+    /// Pause predictions and learning for a while
+    Hold(Duration),
+    /// Cancel the hold
+    Release,
+    /// App that disables automation got focused (`true`), or not anymore (`false`)
+    Disabled(bool),
+    /// Logind reports the session as idle or locked (`true`), or not anymore (`false`)
+    SessionInactive(bool),
+    /// Battery saving limits brightness to this percent, or stopped limiting it (`None`)
+    BrightnessCap(Option<u64>),
+}
+
+/// Brightness change made by another app (e.g. power management), which is followed but not learned.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct External {
+    from: u64,
+    prediction: Option<u64>,
+}
+
+/// Another output that mirrors brightness changes of this one, mapped through a curve.
+pub struct Follower {
+    prediction_tx: Sender<u64>,
+    user_rx: Receiver<u64>,
+    curve: Curve,
+    max_brightness: u64,
+}
+
+impl Follower {
+    pub fn new(
+        prediction_tx: Sender<u64>,
+        user_rx: Receiver<u64>,
+        curve: Curve,
+        max_brightness: u64,
+    ) -> Self {
+        Self {
+            prediction_tx,
+            user_rx,
+            curve,
+            max_brightness,
+        }
+    }
+
+    fn follow(&self, value: u64, max_brightness: u64) {
+        // Changes made directly on the follower are not learned, the next change of the leader overrides them
+        self.user_rx.try_iter().for_each(drop);
+
+        if let Some(percent) = self.curve.apply(value * 100 / max_brightness.max(1)) {
+            self.prediction_tx
+                .send(percent * self.max_brightness / 100)
+                .expect("Unable to send brightness value to follower, channel is dead");
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -32,13 +101,27 @@ impl Controller {
         brightness: Box<dyn Brightness>,
         user_tx: Sender<u64>,
         prediction_rx: Receiver<u64>,
+        event_rx: Receiver<Event>,
+        idle_dim: u64,
+        followers: Vec<Follower>,
+        ignore: Ignore,
     ) -> Self {
         Self {
             brightness,
             user_tx,
             prediction_rx,
+            event_rx,
+            idle_dim,
             current: None,
             target: None,
+            restore: None,
+            followers,
+            ignore,
+            external: None,
+            brightness_cap: None,
+            hold: None,
+            disabled: false,
+            session_inactive: false,
         }
     }
 
@@ -53,17 +136,49 @@ impl Controller {
             Ok(new_brightness) => {
                 let predicted_value = self.prediction_rx.try_iter().last();
 
-                // 1. check if user wants to learn a new value - this overrides any ongoing activity
+                // 1. check if user became idle or active again, wants to put brightness on hold,
+                // or if battery saving started limiting brightness
+                while let Ok(event) = self.event_rx.try_recv() {
+                    match event {
+                        Event::Idle(idle) => self.update_idle(idle),
+                        Event::Hold(duration) => self.update_hold(Some(Instant::now() + duration)),
+                        Event::Release => self.update_hold(None),
+                        Event::Disabled(disabled) => self.update_disabled(disabled),
+                        Event::SessionInactive(inactive) => self.session_inactive = inactive,
+                        Event::BrightnessCap(cap) => self.update_cap(cap),
+                    }
+                }
+                if self.hold.is_some_and(|until| until <= Instant::now()) {
+                    self.update_hold(None);
+                }
+
+                // 2. check if brightness was changed - this overrides any ongoing activity, and is learned
+                // unless the change was made by another app, while user is idle or while paused
                 if Some(new_brightness) != self.current {
-                    return self.update_current(new_brightness);
+                    let external = match self.current {
+                        Some(_) if self.restore.is_some() || self.paused() => true,
+                        Some(current) => self.is_external(current, new_brightness),
+                        None => false,
+                    };
+                    return if external {
+                        self.update_external(new_brightness)
+                    } else {
+                        self.update_current(new_brightness)
+                    };
                 }
 
-                // 2. check if predictor wants to set a new value
+                // 3. check if predictor wants to set a new value, while following another app
+                // only remember it for later, while idle or paused predictions are ignored
                 if let Some(desired) = predicted_value {
-                    self.update_target(desired);
+                    let paused = self.restore.is_some() || self.paused();
+                    match &mut self.external {
+                        _ if paused => {}
+                        Some(external) => external.prediction = Some(desired),
+                        None => self.update_target(desired),
+                    }
                 }
 
-                // 3. continue the transition if there is one in progress
+                // 4. continue the transition if there is one in progress
                 if self.target.is_some() {
                     return self.transition();
                 }
@@ -71,7 +186,7 @@ impl Controller {
             Err(err) => log::error!("Unable to get brightness value: {:?}", err),
         };
 
-        // 4. nothing to do, sleep and check again
+        // 5. nothing to do, sleep and check again
         thread::sleep(Duration::from_millis(WAITING_SLEEP_MS));
     }
 
@@ -81,9 +196,122 @@ impl Controller {
             .send(new_brightness)
             .expect("Unable to send new brightness value set by user, channel is dead");
         self.target = None;
+        self.restore = None;
+        self.external = None;
+        self.follow(new_brightness);
+    }
+
+    /// Changes matching the ignore rules are considered to be made by another app,
+    /// unless hardware reports them as its own (e.g. brightness keys).
+    fn is_external(&mut self, current: u64, new_brightness: u64) -> bool {
+        let percent = |value: u64| value * 100 / self.brightness.max_brightness().max(1);
+
+        let ignored = self.session_inactive
+            || self
+                .ignore
+                .below_percent
+                .is_some_and(|below| percent(new_brightness) < below)
+            || self
+                .ignore
+                .jump_percent
+                .is_some_and(|jump| percent(current.abs_diff(new_brightness)) > jump);
+
+        ignored && !self.brightness.hardware_changed()
+    }
+
+    fn update_external(&mut self, new_brightness: u64) {
+        let external = self.external.take().unwrap_or(External {
+            from: self
+                .current
+                .expect("Current value cannot be None at this point"),
+            prediction: None,
+        });
+
+        self.current = Some(new_brightness);
+        self.target = None;
+        self.follow(new_brightness);
+
+        if external.from == new_brightness {
+            log::debug!(
+                "Brightness was restored to {} by another app",
+                new_brightness
+            );
+            if let Some(desired) = external.prediction {
+                self.update_target(desired);
+            }
+        } else {
+            log::debug!(
+                "Following brightness change to {} made by another app, without learning it",
+                new_brightness
+            );
+            self.external = Some(external);
+        }
+    }
+
+    fn update_idle(&mut self, idle: bool) {
+        match (idle, self.restore, self.current) {
+            (true, None, Some(current)) => {
+                self.restore = Some(current);
+                self.update_target(current * self.idle_dim / 100);
+            }
+            (false, Some(restore), _) => {
+                // Whatever happened while user was idle is forgotten
+                self.restore = None;
+                self.external = None;
+                self.update_target(restore);
+            }
+            _ => {}
+        }
+    }
+
+    fn update_hold(&mut self, until: Option<Instant>) {
+        match until {
+            Some(until) => log::info!(
+                "Brightness is on hold for {}s, it will be neither predicted nor learned",
+                (until - Instant::now()).as_secs()
+            ),
+            None if self.hold.is_some() => {
+                log::info!("Brightness is no longer on hold");
+                // Changes made during the hold are forgotten, predictions will continue from here
+                self.external = None;
+            }
+            None => {}
+        }
+        self.hold = until;
+    }
+
+    fn update_disabled(&mut self, disabled: bool) {
+        if self.disabled && !disabled {
+            // Changes made while the app was focused are forgotten, predictions continue from here
+            self.external = None;
+        }
+        self.disabled = disabled;
+    }
+
+    /// Whether brightness is neither predicted nor learned, on hold or while an app disables it.
+    fn paused(&self) -> bool {
+        self.hold.is_some() || self.disabled
+    }
+
+    fn update_cap(&mut self, cap: Option<u64>) {
+        if self.brightness_cap != cap {
+            self.brightness_cap = cap;
+
+            // Lower the brightness right away, if it is above the new limit
+            if self.restore.is_none() && self.external.is_none() {
+                if let Some(desired) = self.target.map(|t| t.desired).or(self.current) {
+                    self.update_target(desired);
+                }
+            }
+        }
     }
 
     fn update_target(&mut self, desired: u64) {
+        let desired = match self.brightness_cap {
+            Some(cap) => desired.min(cap * self.brightness.max_brightness() / 100),
+            None => desired,
+        };
+
         match (&self.target, self.current) {
             (Some(old_target), _) if old_target.desired == desired => (),
             (_, Some(current)) if desired == current => (),
@@ -94,11 +322,18 @@ impl Controller {
                     -((current - desired).div_ceil(TRANSITION_MAX_MS) as i64)
                 };
                 self.target = Some(Target { desired, step });
+                self.follow(desired);
             }
             _ => unreachable!("Current value cannot be None at this point"),
         };
     }
 
+    fn follow(&self, value: u64) {
+        for follower in &self.followers {
+            follower.follow(value, self.brightness.max_brightness());
+        }
+    }
+
     fn transition(&mut self) {
         match (&self.target, self.current) {
             (Some(target), Some(current)) => {
@@ -138,7 +373,16 @@ mod tests {
     fn setup(brightness_mock: MockBrightness) -> (Controller, Sender<u64>, Receiver<u64>) {
         let (user_tx, user_rx) = mpsc::channel();
         let (prediction_tx, prediction_rx) = mpsc::channel();
-        let controller = Controller::new(Box::new(brightness_mock), user_tx, prediction_rx);
+        let (_, event_rx) = mpsc::channel();
+        let controller = Controller::new(
+            Box::new(brightness_mock),
+            user_tx,
+            prediction_rx,
+            event_rx,
+            100,
+            vec![],
+            Ignore::default(),
+        );
         (controller, prediction_tx, user_rx)
     }
 
@@ -211,6 +455,295 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_step_follows_external_change_without_learning() -> Result<(), Box<dyn Error>> {
+        let mut brightness_mock = MockBrightness::new();
+        let mut values = vec![100, 10, 10, 100].into_iter();
+        brightness_mock
+            .expect_get()
+            .returning(move || Ok(values.next().unwrap()));
+        brightness_mock.expect_max_brightness().return_const(100u64);
+        brightness_mock
+            .expect_hardware_changed()
+            .return_const(false);
+        let (mut controller, prediction_tx, user_rx) = setup(brightness_mock);
+        controller.ignore.jump_percent = Some(50);
+
+        // initial value is learned
+        controller.step();
+        assert_eq!(100, user_rx.try_recv()?);
+
+        // a big jump is followed, but not learned
+        controller.step();
+        assert_eq!(Some(10), controller.current);
+        assert!(user_rx.try_recv().is_err());
+
+        // predictions are only remembered while another app controls brightness
+        prediction_tx.send(80)?;
+        controller.step();
+        assert_eq!(None, controller.target);
+
+        // once the other app restores brightness, the prediction is applied
+        controller.step();
+        assert_eq!(Some(100), controller.current);
+        assert_eq!(None, controller.external);
+        assert_eq!(Some(target(80, -1)), controller.target);
+        assert!(user_rx.try_recv().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_learns_change_made_by_hardware() -> Result<(), Box<dyn Error>> {
+        let mut brightness_mock = MockBrightness::new();
+        brightness_mock.expect_get().return_once(|| Ok(3));
+        brightness_mock.expect_max_brightness().return_const(100u64);
+        brightness_mock.expect_hardware_changed().return_const(true);
+        let (mut controller, _, user_rx) = setup(brightness_mock);
+        controller.ignore.below_percent = Some(5);
+        controller.current = Some(50);
+
+        // brightness keys are a deliberate change, even if it matches the ignore rules
+        controller.step();
+        assert_eq!(3, user_rx.try_recv()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_neither_predicts_nor_learns_while_on_hold() -> Result<(), Box<dyn Error>> {
+        let mut brightness_mock = MockBrightness::new();
+        let mut values = vec![50, 90, 90].into_iter();
+        brightness_mock
+            .expect_get()
+            .returning(move || Ok(values.next().unwrap()));
+        let (mut controller, prediction_tx, user_rx) = setup(brightness_mock);
+        let (event_tx, event_rx) = mpsc::channel();
+        controller.event_rx = event_rx;
+        controller.current = Some(50);
+
+        // when user puts brightness on hold...
+        event_tx.send(Event::Hold(Duration::from_secs(60)))?;
+        controller.step();
+        assert!(controller.hold.is_some());
+
+        // their changes are followed, but not learned
+        controller.step();
+        assert_eq!(Some(90), controller.current);
+        assert!(user_rx.try_recv().is_err());
+
+        // and predictions are paused
+        prediction_tx.send(30)?;
+        controller.step();
+        assert_eq!(None, controller.target);
+
+        // until the hold expires
+        controller.hold = Some(Instant::now());
+        controller.update_hold(None);
+        assert_eq!(None, controller.hold);
+        assert_eq!(None, controller.external);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_does_not_learn_change_made_together_with_hold() -> Result<(), Box<dyn Error>> {
+        let mut brightness_mock = MockBrightness::new();
+        brightness_mock.expect_get().returning(|| Ok(90));
+        let (mut controller, _, user_rx) = setup(brightness_mock);
+        let (event_tx, event_rx) = mpsc::channel();
+        controller.event_rx = event_rx;
+        controller.current = Some(50);
+
+        // when brightness changes right as user puts it on hold...
+        event_tx.send(Event::Hold(Duration::from_secs(60)))?;
+        controller.step();
+
+        // the change is followed, but not learned
+        assert!(controller.hold.is_some());
+        assert_eq!(Some(90), controller.current);
+        assert!(user_rx.try_recv().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_neither_predicts_nor_learns_while_app_disables_automation(
+    ) -> Result<(), Box<dyn Error>> {
+        let mut brightness_mock = MockBrightness::new();
+        let mut values = vec![50, 90, 90].into_iter();
+        brightness_mock
+            .expect_get()
+            .returning(move || Ok(values.next().unwrap()));
+        let (mut controller, prediction_tx, user_rx) = setup(brightness_mock);
+        let (event_tx, event_rx) = mpsc::channel();
+        controller.event_rx = event_rx;
+        controller.current = Some(50);
+
+        // when an app that disables automation gets focused...
+        event_tx.send(Event::Disabled(true))?;
+        controller.step();
+
+        // changes are followed, but not learned
+        controller.step();
+        assert_eq!(Some(90), controller.current);
+        assert!(user_rx.try_recv().is_err());
+
+        // and predictions are ignored
+        prediction_tx.send(30)?;
+        controller.step();
+        assert_eq!(None, controller.target);
+
+        // until another app gets focused
+        controller.update_disabled(false);
+        assert!(!controller.paused());
+        assert_eq!(None, controller.external);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_follows_change_without_learning_while_session_inactive(
+    ) -> Result<(), Box<dyn Error>> {
+        let mut brightness_mock = MockBrightness::new();
+        let mut values = vec![50, 20, 60].into_iter();
+        brightness_mock
+            .expect_get()
+            .returning(move || Ok(values.next().unwrap()));
+        brightness_mock.expect_max_brightness().return_const(100u64);
+        brightness_mock
+            .expect_hardware_changed()
+            .return_const(false);
+        let (mut controller, _, user_rx) = setup(brightness_mock);
+        let (event_tx, event_rx) = mpsc::channel();
+        controller.event_rx = event_rx;
+        controller.current = Some(50);
+        controller.step();
+
+        // desktop environment dims the screen while session is idle
+        event_tx.send(Event::SessionInactive(true))?;
+        controller.step();
+        assert_eq!(Some(20), controller.current);
+        assert!(user_rx.try_recv().is_err());
+
+        // changes are learned again once session becomes active
+        event_tx.send(Event::SessionInactive(false))?;
+        controller.step();
+        assert_eq!(60, user_rx.try_recv()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_cap_limits_brightness() {
+        let mut brightness_mock = MockBrightness::new();
+        brightness_mock.expect_max_brightness().return_const(200u64);
+        let (mut controller, _, _) = setup(brightness_mock);
+        controller.current = Some(150);
+
+        // brightness is lowered as soon as the limit applies...
+        controller.update_cap(Some(50));
+        assert_eq!(Some(target(100, -1)), controller.target);
+
+        // ... and predictions can't exceed it
+        controller.target = None;
+        controller.current = Some(50);
+        controller.update_target(180);
+        assert_eq!(Some(target(100, 1)), controller.target);
+
+        // once the limit no longer applies, predictions are not limited
+        controller.update_cap(None);
+        controller.update_target(180);
+        assert_eq!(Some(target(180, 1)), controller.target);
+    }
+
+    #[test]
+    fn test_update_idle_dims_and_restores_brightness() {
+        let (mut controller, _, _) = setup(MockBrightness::new());
+        controller.idle_dim = 0;
+        controller.current = Some(3);
+
+        controller.update_idle(true);
+        assert_eq!(Some(3), controller.restore);
+        assert_eq!(Some(target(0, -1)), controller.target);
+
+        controller.current = Some(0);
+        controller.target = None;
+
+        controller.update_idle(false);
+        assert_eq!(None, controller.restore);
+        assert_eq!(Some(target(3, 1)), controller.target);
+    }
+
+    #[test]
+    fn test_step_pauses_prediction_while_idle() -> Result<(), Box<dyn Error>> {
+        let mut brightness_mock = MockBrightness::new();
+        brightness_mock.expect_get().returning(|| Ok(50));
+        let (mut controller, prediction_tx, _) = setup(brightness_mock);
+        controller.current = Some(50);
+        controller.restore = Some(100);
+
+        // when predictor wants a change while user is idle...
+        prediction_tx.send(80)?;
+        controller.step();
+
+        // it is not applied, and brightness from before idle will be restored once user is active again
+        assert_eq!(None, controller.target);
+        assert_eq!(Some(100), controller.restore);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_doesnt_learn_while_idle() -> Result<(), Box<dyn Error>> {
+        let mut brightness_mock = MockBrightness::new();
+        brightness_mock.expect_get().returning(|| Ok(10));
+        let (mut controller, _, user_rx) = setup(brightness_mock);
+        controller.current = Some(50);
+        controller.restore = Some(100);
+
+        // when brightness is changed by someone else while user is idle...
+        controller.step();
+
+        // it is followed, but not learned
+        assert_eq!(Some(10), controller.current);
+        assert!(user_rx.try_recv().is_err());
+
+        // and it is forgotten once user is active again
+        controller.update_idle(false);
+        assert_eq!(None, controller.external);
+        assert_eq!(Some(target(100, 1)), controller.target);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_followers_mirror_brightness_through_curve() -> Result<(), Box<dyn Error>> {
+        let mut brightness_mock = MockBrightness::new();
+        brightness_mock.expect_get().return_once(|| Ok(60));
+        brightness_mock.expect_max_brightness().return_const(200u64);
+        let (mut controller, _, _user_rx) = setup(brightness_mock);
+
+        let (follower_tx, follower_rx) = mpsc::channel();
+        let (_, follower_user_rx) = mpsc::channel();
+        let curve = Curve::new(&[(0, 10), (100, 60)].into_iter().collect());
+        controller.followers = vec![Follower::new(follower_tx, follower_user_rx, curve, 1000)];
+
+        // leader is at 30%, which is 25% for the follower
+        controller.step();
+        assert_eq!(250, follower_rx.try_recv()?);
+
+        // leader transitions to 100%, which is 60% for the follower
+        controller.update_target(200);
+        assert_eq!(600, follower_rx.try_recv()?);
+
+        // a repeated prediction is not forwarded again
+        controller.update_target(200);
+        assert!(follower_rx.try_recv().is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_update_target_ignore_when_desired_didnt_change() {
         let old_target = Some(target(10, -20));
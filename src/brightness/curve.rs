@@ -0,0 +1,86 @@
+use itertools::Itertools;
+use std::collections::HashMap;
+
+/// Piecewise-linear mapping between two percentage scales,
+/// values outside of the defined points are clamped to the nearest point.
+#[derive(Debug, Clone)]
+pub struct Curve {
+    points: Vec<(u64, u64)>,
+}
+
+impl Curve {
+    pub fn new(points: &HashMap<u64, u64>) -> Self {
+        Self {
+            points: points
+                .iter()
+                .map(|(&x, &y)| (x, y))
+                .sorted_unstable()
+                .collect(),
+        }
+    }
+
+    pub fn apply(&self, x: u64) -> Option<u64> {
+        let first = self.points.first()?;
+        let last = self.points.last()?;
+
+        if x <= first.0 {
+            return Some(first.1);
+        }
+        if x >= last.0 {
+            return Some(last.1);
+        }
+
+        self.points
+            .iter()
+            .tuple_windows()
+            .find(|((x1, _), (x2, _))| *x1 <= x && x <= *x2)
+            .map(|(&(x1, y1), &(x2, y2))| {
+                let ratio = (x - x1) as f64 / (x2 - x1) as f64;
+                (y1 as f64 + (y2 as f64 - y1 as f64) * ratio).round() as u64
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve(points: &[(u64, u64)]) -> Curve {
+        Curve::new(&points.iter().cloned().collect())
+    }
+
+    #[test]
+    fn test_apply_empty_curve() {
+        assert_eq!(None, curve(&[]).apply(50));
+    }
+
+    #[test]
+    fn test_apply_single_point_is_constant() {
+        let curve = curve(&[(50, 30)]);
+
+        assert_eq!(Some(30), curve.apply(0));
+        assert_eq!(Some(30), curve.apply(50));
+        assert_eq!(Some(30), curve.apply(100));
+    }
+
+    #[test]
+    fn test_apply_interpolates_between_points() {
+        let curve = curve(&[(100, 75), (0, 25), (50, 30)]);
+
+        assert_eq!(Some(25), curve.apply(0));
+        assert_eq!(Some(28), curve.apply(30));
+        assert_eq!(Some(30), curve.apply(50));
+        assert_eq!(Some(53), curve.apply(75));
+        assert_eq!(Some(75), curve.apply(100));
+    }
+
+    #[test]
+    fn test_apply_clamps_outside_of_defined_points() {
+        let curve = curve(&[(20, 10), (80, 90)]);
+
+        assert_eq!(Some(10), curve.apply(0));
+        assert_eq!(Some(10), curve.apply(10));
+        assert_eq!(Some(90), curve.apply(90));
+        assert_eq!(Some(90), curve.apply(100));
+    }
+}
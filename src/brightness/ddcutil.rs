@@ -1,48 +1,212 @@
+use super::Curve;
+use crate::config::{VcpCurve, VcpFeature};
 use ddc_hi::{Ddc, Display, FeatureCode};
 use itertools::Itertools;
 use lazy_static::lazy_static;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::error::Error;
+use std::sync::mpsc::Receiver;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 lazy_static! {
     static ref DDC_MUTEX: Mutex<()> = Mutex::new(());
 }
 
 const DDC_BRIGHTNESS_FEATURE: FeatureCode = 0x10;
+/// How often VCP features follow brightness, so that transitions don't flood the display with DDC commands.
+const FEATURES_SYNC_INTERVAL_MS: u64 = 1000;
 
 pub struct DdcUtil {
     display: RefCell<Display>,
     min_brightness: u64,
     max_brightness: u64,
+    poll: Poll,
+    features: Vec<Feature>,
+    last_features_sync: Instant,
+    als_rx: Receiver<String>,
+    als: Option<String>,
+}
+
+/// Brightness of the display, which is only read over I2C once per poll interval, as reading is slow.
+/// In between, the cached value is kept up to date by our own writes.
+struct Poll {
+    interval: Duration,
+    current: Option<u64>,
+    last: Instant,
+}
+
+impl Poll {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            current: None,
+            last: Instant::now(),
+        }
+    }
+
+    fn get(
+        &mut self,
+        read: impl FnOnce() -> Result<u64, Box<dyn Error>>,
+    ) -> Result<u64, Box<dyn Error>> {
+        if let Some(cached) = self.current {
+            if self.last.elapsed() < self.interval {
+                return Ok(cached);
+            }
+        }
+
+        let value = read()?;
+        if let Some(cached) = self.current.filter(|&cached| cached != value) {
+            log::debug!(
+                "Brightness was changed outside of wluma (e.g. using monitor buttons): {} -> {}",
+                cached,
+                value
+            );
+        }
+
+        self.set(value);
+        Ok(value)
+    }
+
+    fn set(&mut self, value: u64) {
+        self.current = Some(value);
+        self.last = Instant::now();
+    }
+}
+
+struct Feature {
+    code: FeatureCode,
+    curve: FeatureCurve,
+    current: Option<u64>,
+}
+
+enum FeatureCurve {
+    Brightness(Curve),
+    Als(HashMap<String, u64>),
 }
 
 impl DdcUtil {
-    pub fn new(name: &str, min_brightness: u64) -> Result<Self, Box<dyn Error>> {
+    pub fn new(
+        name: &str,
+        min_brightness: u64,
+        poll_interval: Duration,
+        features: Vec<VcpFeature>,
+        als_rx: Receiver<String>,
+    ) -> Result<Self, Box<dyn Error>> {
         let mut display = find_display_by_name(name, true)
             .or_else(|| find_display_by_name(name, false))
             .ok_or("Unable to find display")?;
         let max_brightness = get_max_brightness(&mut display)?;
 
+        let features = features
+            .into_iter()
+            .filter(
+                |feature| match display.handle.get_vcp_feature(feature.code) {
+                    Ok(_) => true,
+                    Err(err) => {
+                        log::warn!(
+                            "Skipping unsupported VCP feature 0x{:02x}: {}",
+                            feature.code,
+                            err
+                        );
+                        false
+                    }
+                },
+            )
+            .map(|feature| Feature {
+                code: feature.code,
+                curve: match feature.curve {
+                    VcpCurve::Brightness(points) => FeatureCurve::Brightness(Curve::new(&points)),
+                    VcpCurve::Als(values) => FeatureCurve::Als(values),
+                },
+                current: None,
+            })
+            .collect();
+
         Ok(Self {
             display: RefCell::new(display),
             min_brightness,
             max_brightness,
+            poll: Poll::new(poll_interval),
+            features,
+            last_features_sync: Instant::now(),
+            als_rx,
+            als: None,
         })
     }
+
+    /// Features are set independently of each other, one that fails is retried on the next sync.
+    fn sync_features(&mut self) {
+        if let Some(als) = self.als_rx.try_iter().last() {
+            self.als = Some(als);
+        }
+
+        let sync_interval = Duration::from_millis(FEATURES_SYNC_INTERVAL_MS);
+        if self.features.is_empty() || self.last_features_sync.elapsed() < sync_interval {
+            return;
+        }
+        self.last_features_sync = Instant::now();
+
+        let brightness_percent = match self.poll.current {
+            Some(current) => current * 100 / self.max_brightness.max(1),
+            None => return,
+        };
+
+        for feature in self.features.iter_mut() {
+            let desired = match &feature.curve {
+                FeatureCurve::Brightness(curve) => curve.apply(brightness_percent),
+                FeatureCurve::Als(values) => {
+                    self.als.as_ref().and_then(|als| values.get(als)).copied()
+                }
+            };
+
+            let desired = match desired {
+                Some(desired) => desired,
+                None => continue,
+            };
+
+            if feature.current != Some(desired) {
+                let _lock = DDC_MUTEX
+                    .lock()
+                    .expect("Unable to acquire exclusive access to DDC API");
+                match self
+                    .display
+                    .borrow_mut()
+                    .handle
+                    .set_vcp_feature(feature.code, desired as u16)
+                {
+                    Ok(()) => {
+                        log::debug!("Set VCP feature 0x{:02x} to {}", feature.code, desired);
+                        feature.current = Some(desired);
+                    }
+                    Err(err) => log::error!(
+                        "Unable to set VCP feature 0x{:02x} to {}: {:?}",
+                        feature.code,
+                        desired,
+                        err
+                    ),
+                }
+            }
+        }
+    }
 }
 
 impl super::Brightness for DdcUtil {
     fn get(&mut self) -> Result<u64, Box<dyn Error>> {
-        let _lock = DDC_MUTEX
-            .lock()
-            .expect("Unable to acquire exclusive access to DDC API");
-        Ok(self
-            .display
-            .borrow_mut()
-            .handle
-            .get_vcp_feature(DDC_BRIGHTNESS_FEATURE)?
-            .value() as u64)
+        self.sync_features();
+
+        let display = &self.display;
+        self.poll.get(|| {
+            let _lock = DDC_MUTEX
+                .lock()
+                .expect("Unable to acquire exclusive access to DDC API");
+            Ok(display
+                .borrow_mut()
+                .handle
+                .get_vcp_feature(DDC_BRIGHTNESS_FEATURE)?
+                .value() as u64)
+        })
     }
 
     fn set(&mut self, value: u64) -> Result<u64, Box<dyn Error>> {
@@ -54,8 +218,15 @@ impl super::Brightness for DdcUtil {
             .borrow_mut()
             .handle
             .set_vcp_feature(DDC_BRIGHTNESS_FEATURE, value as u16)?;
+
+        // The written value is not read back from the display until the next poll
+        self.poll.set(value);
         Ok(value)
     }
+
+    fn max_brightness(&self) -> u64 {
+        self.max_brightness
+    }
 }
 
 fn get_max_brightness(display: &mut Display) -> Result<u64, Box<dyn Error>> {
@@ -107,3 +278,46 @@ fn find_display_by_name(name: &str, check_caps: bool) -> Option<Display> {
             .map(|_| display)
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_poll_reads_once_per_interval() -> Result<(), Box<dyn Error>> {
+        let reads = Cell::new(0);
+        let read = |value: u64| {
+            reads.set(reads.get() + 1);
+            Ok(value)
+        };
+        let mut poll = Poll::new(Duration::from_secs(60));
+
+        assert_eq!(50, poll.get(|| read(50))?);
+        assert_eq!(50, poll.get(|| read(80))?);
+        assert_eq!(1, reads.get());
+
+        // Our own writes are cached without reading them back
+        poll.set(70);
+        assert_eq!(70, poll.get(|| read(80))?);
+        assert_eq!(1, reads.get());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_notices_external_change() -> Result<(), Box<dyn Error>> {
+        let mut poll = Poll::new(Duration::ZERO);
+        poll.set(70);
+
+        // Brightness was changed using monitor buttons
+        assert_eq!(30, poll.get(|| Ok(30))?);
+        assert_eq!(Some(30), poll.current);
+
+        // Failed reads keep the cached value
+        assert!(poll.get(|| Err("I2C error".into())).is_err());
+        assert_eq!(Some(30), poll.current);
+
+        Ok(())
+    }
+}
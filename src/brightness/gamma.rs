@@ -0,0 +1,273 @@
+use super::RateLimited;
+use std::error::Error;
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::os::fd::{AsFd, FromRawFd};
+use std::time::Duration;
+use wayland_client::protocol::wl_output::WlOutput;
+use wayland_client::protocol::wl_registry::WlRegistry;
+use wayland_client::{Connection, Dispatch, EventQueue, Proxy, QueueHandle};
+use wayland_protocols_wlr::gamma_control::v1::client::zwlr_gamma_control_manager_v1::ZwlrGammaControlManagerV1;
+use wayland_protocols_wlr::gamma_control::v1::client::zwlr_gamma_control_v1::ZwlrGammaControlV1;
+
+const MAX_BRIGHTNESS: u64 = 100;
+/// Every change is a blocking roundtrip to the compositor, so transitions are applied at most this often.
+const SET_INTERVAL_MS: u64 = 16;
+
+pub struct Gamma {
+    event_queue: EventQueue<State>,
+    state: State,
+    control: ZwlrGammaControlV1,
+    gamma_size: u32,
+    ramp_file: File,
+    min_brightness: u64,
+    current: u64,
+    limit: RateLimited,
+}
+
+struct State {
+    desired_output: String,
+    output: Option<WlOutput>,
+    manager: Option<ZwlrGammaControlManagerV1>,
+    gamma_size: Option<u32>,
+    failed: bool,
+}
+
+impl Gamma {
+    pub fn new(name: &str, min_brightness: u64) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::connect_to_env()?;
+        let mut event_queue = connection.new_event_queue();
+        let qh = event_queue.handle();
+
+        let mut state = State {
+            desired_output: name.to_string(),
+            output: None,
+            manager: None,
+            gamma_size: None,
+            failed: false,
+        };
+
+        connection.display().get_registry(&qh, ());
+
+        // 1. process registry events
+        event_queue.roundtrip(&mut state)?;
+        // 2. registry requested wl_output events, process those
+        event_queue.roundtrip(&mut state)?;
+
+        let manager = state
+            .manager
+            .clone()
+            .ok_or("Compositor does not support wlr-gamma-control-unstable-v1 protocol")?;
+        let output = state.output.clone().ok_or("Unable to find output")?;
+
+        let control = manager.get_gamma_control(&output, &qh, ());
+        event_queue.roundtrip(&mut state)?;
+
+        let gamma_size = match (state.failed, state.gamma_size) {
+            (false, Some(gamma_size)) => gamma_size,
+            _ => Err("Unable to control gamma, is another application already using it?")?,
+        };
+
+        // Ramps always have the same size, so one file is rewritten for every change
+        let fd = unsafe { libc::memfd_create(c"wluma-gamma".as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            Err(std::io::Error::last_os_error())?;
+        }
+        let ramp_file = unsafe { File::from_raw_fd(fd) };
+
+        Ok(Self {
+            event_queue,
+            state,
+            control,
+            gamma_size,
+            ramp_file,
+            min_brightness,
+            current: MAX_BRIGHTNESS,
+            limit: RateLimited::new(Duration::from_millis(SET_INTERVAL_MS)),
+        })
+    }
+
+    fn write(&mut self, value: u64) -> Result<(), Box<dyn Error>> {
+        if self.state.failed {
+            Err("Gamma control is no longer valid, is another application using it?")?;
+        }
+
+        self.ramp_file.seek(SeekFrom::Start(0))?;
+        self.ramp_file.write_all(&ramp(self.gamma_size, value))?;
+        self.ramp_file.seek(SeekFrom::Start(0))?;
+        self.control.set_gamma(self.ramp_file.as_fd());
+        // Compositor must have read the ramp before the file is rewritten
+        self.event_queue.roundtrip(&mut self.state)?;
+
+        self.current = value;
+        self.limit.written();
+        Ok(())
+    }
+}
+
+/// Red, green and blue ramps (which are identical) that scale colors to the given brightness percentage.
+fn ramp(gamma_size: u32, value: u64) -> Vec<u8> {
+    let last = (gamma_size.max(2) - 1) as f64;
+    let factor = value as f64 / MAX_BRIGHTNESS as f64;
+    let ramp = (0..gamma_size)
+        .flat_map(|i| {
+            let value = (i as f64 / last * u16::MAX as f64 * factor).round() as u16;
+            value.to_ne_bytes()
+        })
+        .collect::<Vec<_>>();
+
+    ramp.repeat(3)
+}
+
+impl super::Brightness for Gamma {
+    fn get(&mut self) -> Result<u64, Box<dyn Error>> {
+        match self.limit.pending() {
+            Some((pending, false)) => return Ok(pending),
+            Some((pending, true)) => self.write(pending)?,
+            None => {}
+        }
+
+        Ok(self.current)
+    }
+
+    fn set(&mut self, value: u64) -> Result<u64, Box<dyn Error>> {
+        let value = value.clamp(self.min_brightness, MAX_BRIGHTNESS);
+
+        if self.limit.allows(value) {
+            self.write(value)?;
+        }
+
+        Ok(value)
+    }
+
+    fn max_brightness(&self) -> u64 {
+        MAX_BRIGHTNESS
+    }
+}
+
+impl Dispatch<WlRegistry, ()> for State {
+    fn event(
+        state: &mut Self,
+        registry: &WlRegistry,
+        event: <WlRegistry as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        use wayland_client::protocol::wl_registry::Event;
+
+        if let Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            match &interface[..] {
+                _ if interface == WlOutput::interface().name => {
+                    registry.bind::<WlOutput, _, _>(name, version, qh, ());
+                }
+                _ if interface == ZwlrGammaControlManagerV1::interface().name => {
+                    state.manager = Some(registry.bind::<ZwlrGammaControlManagerV1, _, _>(
+                        name,
+                        version,
+                        qh,
+                        (),
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Dispatch<WlOutput, ()> for State {
+    fn event(
+        state: &mut Self,
+        output: &WlOutput,
+        event: <WlOutput as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use wayland_client::protocol::wl_output::Event;
+
+        match event {
+            Event::Description { description }
+                if state.output.is_none() && description.contains(&state.desired_output) =>
+            {
+                log::debug!(
+                    "Using output '{}' for gamma config '{}'",
+                    description,
+                    state.desired_output,
+                );
+                state.output = Some(output.clone());
+            }
+
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrGammaControlManagerV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrGammaControlManagerV1,
+        _: <ZwlrGammaControlManagerV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrGammaControlV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrGammaControlV1,
+        event: <ZwlrGammaControlV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use wayland_protocols_wlr::gamma_control::v1::client::zwlr_gamma_control_v1::Event;
+
+        match event {
+            Event::GammaSize { size } => state.gamma_size = Some(size),
+            Event::Failed => {
+                log::error!("Gamma control for '{}' failed", state.desired_output);
+                state.failed = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(ramp: &[u8], size: usize, channel: usize) -> Vec<u16> {
+        ramp[channel * size * 2..(channel + 1) * size * 2]
+            .chunks_exact(2)
+            .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn test_ramp_full_brightness() {
+        let ramp = ramp(3, 100);
+
+        assert_eq!(3 * 3 * 2, ramp.len());
+        for c in 0..3 {
+            assert_eq!(vec![0, 32768, u16::MAX], channel(&ramp, 3, c));
+        }
+    }
+
+    #[test]
+    fn test_ramp_scales_colors() {
+        let ramp = ramp(5, 50);
+
+        assert_eq!(vec![0, 8192, 16384, 24576, 32768], channel(&ramp, 5, 0));
+        assert_eq!(channel(&ramp, 5, 0), channel(&ramp, 5, 2));
+    }
+}
@@ -1,18 +1,78 @@
 use std::error::Error;
+use std::time::{Duration, Instant};
 
 #[cfg(test)]
 use mockall::*;
 
 mod backlight;
+mod command;
 mod controller;
+mod curve;
 mod ddcutil;
+mod gamma;
+mod stacked;
 
-pub use backlight::Backlight;
-pub use controller::Controller;
+pub use backlight::{
+    find_backlight, find_keyboard_backlight, Backlight, BACKLIGHT_CLASS_PATH, LEDS_CLASS_PATH,
+};
+pub use command::Command;
+pub use controller::{Controller, Event, Follower};
+pub use curve::Curve;
 pub use ddcutil::DdcUtil;
+pub use gamma::Gamma;
+pub use stacked::Stacked;
 
 #[cfg_attr(test, automock)]
 pub trait Brightness {
     fn get(&mut self) -> Result<u64, Box<dyn Error>>;
     fn set(&mut self, value: u64) -> Result<u64, Box<dyn Error>>;
+    fn max_brightness(&self) -> u64;
+
+    /// Whether the last change returned by `get` was made by hardware itself (e.g. brightness keys),
+    /// which is always a deliberate user adjustment.
+    fn hardware_changed(&mut self) -> bool {
+        false
+    }
+}
+
+/// Limits how often a slow device is written to. Values requested in the meantime, e.g. during
+/// a transition, are not written one by one, only the last one is once the device may be written again.
+struct RateLimited {
+    interval: Duration,
+    pending: Option<u64>,
+    last_write: Option<Instant>,
+}
+
+impl RateLimited {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            pending: None,
+            last_write: None,
+        }
+    }
+
+    /// Whether the value can be written right away, otherwise it is kept as pending.
+    fn allows(&mut self, value: u64) -> bool {
+        if self.can_write() {
+            return true;
+        }
+        self.pending = Some(value);
+        false
+    }
+
+    /// The last value requested while writing was not allowed, and whether it can be written now.
+    fn pending(&self) -> Option<(u64, bool)> {
+        self.pending.map(|pending| (pending, self.can_write()))
+    }
+
+    fn written(&mut self) {
+        self.pending = None;
+        self.last_write = Some(Instant::now());
+    }
+
+    fn can_write(&self) -> bool {
+        self.last_write
+            .map_or(true, |last_write| last_write.elapsed() >= self.interval)
+    }
 }
@@ -0,0 +1,167 @@
+use super::{Backlight, Brightness, Gamma};
+use std::error::Error;
+
+const GAMMA_MAX: u64 = 100;
+
+/// Hardware backlight with software dimming (gamma) stacked underneath it.
+///
+/// The lower part of the value range `[0, extension]` keeps the backlight at its minimum
+/// while scaling gamma from `gamma_min` up to 100%, the rest of the range is the backlight itself.
+pub struct Stacked<B = Backlight, G = Gamma> {
+    backlight: B,
+    hardware_min: u64,
+    gamma: G,
+    gamma_min: u64,
+    extension: u64,
+    software: u64,
+}
+
+impl Stacked {
+    pub fn new(backlight: Backlight, gamma: Gamma, gamma_min: u64) -> Self {
+        let hardware_min = backlight.min_brightness();
+        Self::with_hardware_min(backlight, hardware_min, gamma, gamma_min)
+    }
+}
+
+impl<B: Brightness, G: Brightness> Stacked<B, G> {
+    fn with_hardware_min(backlight: B, hardware_min: u64, gamma: G, gamma_min: u64) -> Self {
+        let gamma_min = gamma_min.min(GAMMA_MAX);
+        let extension = backlight.max_brightness() * (GAMMA_MAX - gamma_min) / GAMMA_MAX;
+
+        Self {
+            backlight,
+            hardware_min,
+            gamma,
+            gamma_min,
+            extension,
+            software: extension,
+        }
+    }
+
+    fn set_software(&mut self, value: u64) -> Result<(), Box<dyn Error>> {
+        if self.software != value {
+            let percent = match self.extension {
+                0 => GAMMA_MAX,
+                extension => self.gamma_min + (GAMMA_MAX - self.gamma_min) * value / extension,
+            };
+            self.gamma.set(percent)?;
+            self.software = value;
+        }
+        Ok(())
+    }
+}
+
+impl<B: Brightness, G: Brightness> Brightness for Stacked<B, G> {
+    fn get(&mut self) -> Result<u64, Box<dyn Error>> {
+        let hardware = self.backlight.get()?;
+        let hardware_min = self.hardware_min;
+
+        if hardware > hardware_min {
+            // Brightness was raised above the hardware minimum, software dimming is no longer needed
+            self.set_software(self.extension)?;
+            Ok(self.extension + hardware - hardware_min)
+        } else {
+            Ok(self.software)
+        }
+    }
+
+    fn set(&mut self, value: u64) -> Result<u64, Box<dyn Error>> {
+        let hardware_min = self.hardware_min;
+
+        if value > self.extension {
+            let hardware = self.backlight.set(hardware_min + value - self.extension)?;
+            self.set_software(self.extension)?;
+            Ok(self.extension + hardware - hardware_min)
+        } else {
+            self.backlight.set(hardware_min)?;
+            self.set_software(value)?;
+            Ok(value)
+        }
+    }
+
+    fn max_brightness(&self) -> u64 {
+        self.extension + self.backlight.max_brightness() - self.hardware_min
+    }
+
+    fn hardware_changed(&mut self) -> bool {
+        self.backlight.hardware_changed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MockBrightness;
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Backlight with range 1..=100 and gamma scaled from 40%, both remembering values they were set to.
+    fn setup(
+        hardware: u64,
+    ) -> (
+        Stacked<MockBrightness, MockBrightness>,
+        Arc<Mutex<Vec<u64>>>,
+    ) {
+        let mut backlight = MockBrightness::new();
+        backlight.expect_max_brightness().return_const(100u64);
+        backlight.expect_get().returning(move || Ok(hardware));
+        backlight.expect_set().returning(Ok);
+
+        let gamma_values = Arc::new(Mutex::new(vec![]));
+        let mut gamma = MockBrightness::new();
+        let values = gamma_values.clone();
+        gamma.expect_set().returning(move |value| {
+            values.lock().unwrap().push(value);
+            Ok(value)
+        });
+
+        (
+            Stacked::with_hardware_min(backlight, 1, gamma, 40),
+            gamma_values,
+        )
+    }
+
+    #[test]
+    fn test_max_brightness_extends_backlight_range() {
+        let (stacked, _) = setup(1);
+
+        // 60 steps of gamma below the 100 steps of the backlight
+        assert_eq!(60, stacked.extension);
+        assert_eq!(159, stacked.max_brightness());
+    }
+
+    #[test]
+    fn test_set_below_extension_dims_gamma() -> Result<(), Box<dyn Error>> {
+        let (mut stacked, gamma_values) = setup(1);
+
+        assert_eq!(30, stacked.set(30)?);
+        assert_eq!(vec![70], *gamma_values.lock().unwrap());
+        assert_eq!(0, stacked.set(0)?);
+        assert_eq!(vec![70, 40], *gamma_values.lock().unwrap());
+        assert_eq!(0, stacked.get()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_above_extension_restores_gamma() -> Result<(), Box<dyn Error>> {
+        let (mut stacked, gamma_values) = setup(1);
+        stacked.set(30)?;
+
+        // The rest of the range is the backlight, with full gamma
+        assert_eq!(100, stacked.set(100)?);
+        assert_eq!(vec![70, 100], *gamma_values.lock().unwrap());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_after_backlight_raised_elsewhere() -> Result<(), Box<dyn Error>> {
+        let (mut stacked, gamma_values) = setup(41);
+        stacked.software = 30;
+
+        assert_eq!(100, stacked.get()?);
+        assert_eq!(vec![100], *gamma_values.lock().unwrap());
+
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fmt};
+use std::{collections::HashMap, fmt, time::Duration};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum WaylandProtocol {
@@ -47,16 +47,57 @@ pub enum Predictor {
     Adaptive,
     Manual {
         thresholds: HashMap<String, HashMap<u8, u64>>,
+        mode: ManualMode,
     },
+    Levels {
+        levels: HashMap<String, u64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManualMode {
+    /// Thresholds reduce brightness that user set by a percentage of it
+    Reduction,
+    /// Thresholds are percentages of the maximum brightness, user changes shift them
+    Absolute,
+}
+
+#[derive(Debug, Clone)]
+pub struct Idle {
+    pub timeout: Duration,
+    pub dim: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Follow {
+    pub leader: String,
+    pub curve: HashMap<u64, u64>,
 }
 
 #[derive(Debug, Clone)]
 pub struct BacklightOutput {
     pub name: String,
-    pub path: String,
+    /// Device is discovered when the path is not configured.
+    pub path: Option<String>,
+    pub keyboard: bool,
     pub capturer: Capturer,
     pub min_brightness: u64,
     pub predictor: Predictor,
+    pub gamma_min: Option<u64>,
+    pub idle: Option<Idle>,
+    pub follow: Option<Follow>,
+}
+
+#[derive(Debug, Clone)]
+pub enum VcpCurve {
+    Brightness(HashMap<u64, u64>),
+    Als(HashMap<String, u64>),
+}
+
+#[derive(Debug, Clone)]
+pub struct VcpFeature {
+    pub code: u8,
+    pub curve: VcpCurve,
 }
 
 #[derive(Debug, Clone)]
@@ -65,16 +106,96 @@ pub struct DdcUtilOutput {
     pub capturer: Capturer,
     pub min_brightness: u64,
     pub predictor: Predictor,
+    pub poll_interval: Duration,
+    pub vcp: Vec<VcpFeature>,
+    pub follow: Option<Follow>,
+    pub idle: Option<Idle>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GammaOutput {
+    pub name: String,
+    pub capturer: Capturer,
+    pub min_brightness: u64,
+    pub predictor: Predictor,
+    pub follow: Option<Follow>,
+    pub idle: Option<Idle>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandOutput {
+    pub name: String,
+    pub capturer: Capturer,
+    pub min_brightness: u64,
+    pub max_brightness: u64,
+    pub predictor: Predictor,
+    pub get: String,
+    pub set: String,
+    pub timeout: Duration,
+    pub poll_interval: Duration,
+    pub set_interval: Duration,
+    pub follow: Option<Follow>,
+    pub idle: Option<Idle>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Output {
     Backlight(BacklightOutput),
     DdcUtil(DdcUtilOutput),
+    Gamma(GammaOutput),
+    Command(CommandOutput),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Ignore {
+    pub session_inactive: bool,
+    pub jump_percent: Option<u64>,
+    pub below_percent: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Power {
+    pub separate_data: bool,
+    pub below_percent: Option<u64>,
+    pub max_brightness_percent: Option<u64>,
+    pub slowdown: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Adaptive {
+    pub time_of_day: bool,
+    pub half_life: Option<Duration>,
+    pub max_age: Option<Duration>,
+    pub cross_profile_confidence: f64,
+    pub monotonic: bool,
+    pub confirm: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cooldown {
+    pub adjustment: Duration,
+    pub als: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Apps {
+    pub separate_data: bool,
+    pub disabled: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hold {
+    pub duration: Duration,
 }
 
 #[derive(Debug)]
 pub struct Config {
     pub als: Als,
+    pub ignore: Ignore,
+    pub power: Power,
+    pub hold: Hold,
+    pub adaptive: Adaptive,
+    pub cooldown: Cooldown,
+    pub apps: Apps,
     pub output: Vec<Output>,
 }
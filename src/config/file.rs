@@ -40,6 +40,8 @@ pub enum Als {
 pub struct OutputByType {
     pub backlight: Vec<BacklightOutput>,
     pub ddcutil: Vec<DdcUtilOutput>,
+    pub gamma: Vec<GammaOutput>,
+    pub command: Vec<CommandOutput>,
 }
 
 #[derive(Deserialize, Debug, Default)]
@@ -49,15 +51,43 @@ pub enum Predictor {
     Adaptive,
     Manual {
         thresholds: HashMap<String, HashMap<String, u64>>,
+        mode: Option<ManualMode>,
     },
 }
 
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ManualMode {
+    #[default]
+    Reduction,
+    Absolute,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct BacklightOutput {
     pub name: String,
-    pub path: String,
+    pub path: Option<String>,
     pub capturer: Option<Capturer>,
     pub predictor: Option<Predictor>,
+    pub gamma_min: Option<u64>,
+    pub follow: Option<String>,
+    pub follow_curve: Option<HashMap<String, u64>>,
+    pub idle_timeout_secs: Option<u64>,
+    pub idle_dim_percent: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum VcpCurve {
+    Brightness(HashMap<String, u64>),
+    Als(HashMap<String, u64>),
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VcpFeature {
+    pub code: u8,
+    #[serde(flatten)]
+    pub curve: VcpCurve,
 }
 
 #[derive(Deserialize, Debug)]
@@ -65,18 +95,119 @@ pub struct DdcUtilOutput {
     pub name: String,
     pub capturer: Option<Capturer>,
     pub predictor: Option<Predictor>,
+    pub poll_interval_ms: Option<u64>,
+    #[serde(default)]
+    pub vcp: Vec<VcpFeature>,
+    pub follow: Option<String>,
+    pub follow_curve: Option<HashMap<String, u64>>,
+    pub idle_timeout_secs: Option<u64>,
+    pub idle_dim_percent: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GammaOutput {
+    pub name: String,
+    pub capturer: Option<Capturer>,
+    pub predictor: Option<Predictor>,
+    pub follow: Option<String>,
+    pub follow_curve: Option<HashMap<String, u64>>,
+    pub idle_timeout_secs: Option<u64>,
+    pub idle_dim_percent: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommandOutput {
+    pub name: String,
+    pub get: String,
+    pub set: String,
+    pub max: u64,
+    pub timeout_ms: Option<u64>,
+    pub poll_interval_ms: Option<u64>,
+    pub set_interval_ms: Option<u64>,
+    pub capturer: Option<Capturer>,
+    pub predictor: Option<Predictor>,
+    pub follow: Option<String>,
+    pub follow_curve: Option<HashMap<String, u64>>,
+    pub idle_timeout_secs: Option<u64>,
+    pub idle_dim_percent: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyboardPredictor {
+    #[default]
+    Adaptive,
+    Levels {
+        levels: HashMap<String, u64>,
+    },
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Keyboard {
     pub name: String,
-    pub path: String,
+    pub path: Option<String>,
+    pub predictor: Option<KeyboardPredictor>,
+    pub idle_timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Ignore {
+    pub session_inactive: Option<bool>,
+    pub jump_percent: Option<u64>,
+    pub below_percent: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Power {
+    pub separate_data: Option<bool>,
+    pub below_percent: Option<u64>,
+    pub max_brightness_percent: Option<u64>,
+    pub slowdown: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Adaptive {
+    pub time_of_day: Option<bool>,
+    pub half_life_days: Option<u64>,
+    pub max_age_days: Option<u64>,
+    pub cross_profile_confidence: Option<f64>,
+    pub monotonic: Option<bool>,
+    pub confirm: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Cooldown {
+    pub adjustment_ms: Option<u64>,
+    pub als_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Apps {
+    pub separate_data: Option<bool>,
+    pub disabled: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Hold {
+    pub minutes: Option<u64>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub als: Als,
     #[serde(default)]
+    pub ignore: Ignore,
+    #[serde(default)]
+    pub power: Power,
+    #[serde(default)]
+    pub hold: Hold,
+    #[serde(default)]
+    pub adaptive: Adaptive,
+    #[serde(default)]
+    pub cooldown: Cooldown,
+    #[serde(default)]
+    pub apps: Apps,
+    #[serde(default)]
     pub output: OutputByType,
     #[serde(default)]
     pub keyboard: Vec<Keyboard>,
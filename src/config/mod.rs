@@ -2,10 +2,21 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::error::Error;
 use std::fs;
+use std::time::Duration;
 mod app;
 mod file;
 pub use app::*;
 
+const DEFAULT_DDC_POLL_INTERVAL_MS: u64 = 1000;
+const DEFAULT_COMMAND_TIMEOUT_MS: u64 = 1000;
+const DEFAULT_COMMAND_POLL_INTERVAL_MS: u64 = 1000;
+const DEFAULT_COMMAND_SET_INTERVAL_MS: u64 = 100;
+const DEFAULT_IDLE_DIM_PERCENT: u64 = 50;
+const DEFAULT_HOLD_MINUTES: u64 = 30;
+const DEFAULT_CROSS_PROFILE_CONFIDENCE: f64 = 0.5;
+const DEFAULT_ADJUSTMENT_COOLDOWN_MS: u64 = 1500;
+const DEFAULT_ALS_COOLDOWN_MS: u64 = 1500;
+
 pub fn load() -> Result<app::Config, Box<dyn Error>> {
     validate(parse()?)
 }
@@ -13,7 +24,7 @@ pub fn load() -> Result<app::Config, Box<dyn Error>> {
 fn match_predictor(predictor: file::Predictor) -> app::Predictor {
     match predictor {
         file::Predictor::Adaptive => app::Predictor::Adaptive,
-        file::Predictor::Manual { thresholds } => app::Predictor::Manual {
+        file::Predictor::Manual { thresholds, mode } => app::Predictor::Manual {
             thresholds: thresholds
                 .into_iter()
                 .map(|(k, v)| {
@@ -25,10 +36,63 @@ fn match_predictor(predictor: file::Predictor) -> app::Predictor {
                     )
                 })
                 .collect(),
+            mode: match mode.unwrap_or_default() {
+                file::ManualMode::Reduction => app::ManualMode::Reduction,
+                file::ManualMode::Absolute => app::ManualMode::Absolute,
+            },
+        },
+    }
+}
+
+fn match_keyboard_predictor(predictor: file::KeyboardPredictor) -> app::Predictor {
+    match predictor {
+        file::KeyboardPredictor::Adaptive => app::Predictor::Adaptive,
+        file::KeyboardPredictor::Levels { levels } => app::Predictor::Levels { levels },
+    }
+}
+
+fn match_vcp_feature(feature: file::VcpFeature) -> app::VcpFeature {
+    app::VcpFeature {
+        code: feature.code,
+        curve: match feature.curve {
+            file::VcpCurve::Brightness(points) => app::VcpCurve::Brightness(
+                points
+                    .into_iter()
+                    .map(|(k, v)| (k.parse().unwrap(), v))
+                    .collect(),
+            ),
+            file::VcpCurve::Als(values) => app::VcpCurve::Als(values),
         },
     }
 }
 
+fn match_follow(
+    leader: Option<String>,
+    curve: Option<HashMap<String, u64>>,
+) -> Option<app::Follow> {
+    leader.map(|leader| app::Follow {
+        leader,
+        curve: match curve {
+            Some(curve) => curve
+                .into_iter()
+                .map(|(k, v)| (k.parse().unwrap(), v))
+                .collect(),
+            None => HashMap::from([(0, 0), (100, 100)]),
+        },
+    })
+}
+
+fn match_idle(timeout_secs: Option<u64>, dim_percent: Option<u64>) -> Option<app::Idle> {
+    timeout_secs.map(|secs| app::Idle {
+        timeout: Duration::from_secs(secs),
+        dim: dim_percent.unwrap_or(DEFAULT_IDLE_DIM_PERCENT).min(100),
+    })
+}
+
+fn days(days: u64) -> Duration {
+    Duration::from_secs(days * 24 * 60 * 60)
+}
+
 fn match_capturer(capturer: file::Capturer) -> app::Capturer {
     match capturer {
         file::Capturer::None => app::Capturer::None,
@@ -73,9 +137,13 @@ fn parse() -> Result<app::Config, toml::de::Error> {
                 app::Output::Backlight(app::BacklightOutput {
                     name: o.name,
                     path: o.path,
+                    keyboard: false,
                     min_brightness: 1,
                     capturer: match_capturer(o.capturer.unwrap_or_default()),
                     predictor: match_predictor(o.predictor.unwrap_or_default()),
+                    follow: match_follow(o.follow, o.follow_curve),
+                    gamma_min: o.gamma_min,
+                    idle: match_idle(o.idle_timeout_secs, o.idle_dim_percent),
                 })
             })
             .chain(file_config.output.ddcutil.into_iter().map(|o| {
@@ -84,19 +152,115 @@ fn parse() -> Result<app::Config, toml::de::Error> {
                     min_brightness: 1,
                     capturer: match_capturer(o.capturer.unwrap_or_default()),
                     predictor: match_predictor(o.predictor.unwrap_or_default()),
+                    follow: match_follow(o.follow, o.follow_curve),
+                    poll_interval: Duration::from_millis(
+                        o.poll_interval_ms.unwrap_or(DEFAULT_DDC_POLL_INTERVAL_MS),
+                    ),
+                    vcp: o.vcp.into_iter().map(match_vcp_feature).collect(),
+                    idle: match_idle(o.idle_timeout_secs, o.idle_dim_percent),
+                })
+            }))
+            .chain(file_config.output.gamma.into_iter().map(|o| {
+                app::Output::Gamma(app::GammaOutput {
+                    name: o.name,
+                    min_brightness: 10,
+                    capturer: match_capturer(o.capturer.unwrap_or_default()),
+                    predictor: match_predictor(o.predictor.unwrap_or_default()),
+                    follow: match_follow(o.follow, o.follow_curve),
+                    idle: match_idle(o.idle_timeout_secs, o.idle_dim_percent),
+                })
+            }))
+            .chain(file_config.output.command.into_iter().map(|o| {
+                app::Output::Command(app::CommandOutput {
+                    name: o.name,
+                    min_brightness: 1,
+                    max_brightness: o.max,
+                    capturer: match_capturer(o.capturer.unwrap_or_default()),
+                    predictor: match_predictor(o.predictor.unwrap_or_default()),
+                    follow: match_follow(o.follow, o.follow_curve),
+                    get: o.get,
+                    set: o.set,
+                    timeout: Duration::from_millis(
+                        o.timeout_ms.unwrap_or(DEFAULT_COMMAND_TIMEOUT_MS),
+                    ),
+                    poll_interval: Duration::from_millis(
+                        o.poll_interval_ms
+                            .unwrap_or(DEFAULT_COMMAND_POLL_INTERVAL_MS),
+                    ),
+                    set_interval: Duration::from_millis(
+                        o.set_interval_ms.unwrap_or(DEFAULT_COMMAND_SET_INTERVAL_MS),
+                    ),
+                    idle: match_idle(o.idle_timeout_secs, o.idle_dim_percent),
                 })
             }))
             .chain(file_config.keyboard.into_iter().map(|k| {
                 app::Output::Backlight(app::BacklightOutput {
                     name: k.name,
                     path: k.path,
+                    keyboard: true,
                     min_brightness: 0,
                     capturer: Capturer::None,
-                    predictor: app::Predictor::Adaptive,
+                    predictor: match_keyboard_predictor(k.predictor.unwrap_or_default()),
+                    gamma_min: None,
+                    follow: None,
+                    // Keyboard backlight is turned off completely
+                    idle: match_idle(k.idle_timeout_secs, Some(0)),
                 })
             }))
             .collect(),
 
+        ignore: app::Ignore {
+            session_inactive: file_config.ignore.session_inactive.unwrap_or(false),
+            jump_percent: file_config.ignore.jump_percent,
+            below_percent: file_config.ignore.below_percent,
+        },
+
+        power: app::Power {
+            separate_data: file_config.power.separate_data.unwrap_or(false),
+            below_percent: file_config.power.below_percent,
+            max_brightness_percent: file_config.power.max_brightness_percent,
+            slowdown: file_config.power.slowdown.unwrap_or(1),
+        },
+
+        hold: app::Hold {
+            duration: Duration::from_secs(
+                file_config.hold.minutes.unwrap_or(DEFAULT_HOLD_MINUTES) * 60,
+            ),
+        },
+
+        adaptive: app::Adaptive {
+            time_of_day: file_config.adaptive.time_of_day.unwrap_or(false),
+            half_life: file_config.adaptive.half_life_days.map(days),
+            max_age: file_config.adaptive.max_age_days.map(days),
+            cross_profile_confidence: file_config
+                .adaptive
+                .cross_profile_confidence
+                .unwrap_or(DEFAULT_CROSS_PROFILE_CONFIDENCE)
+                .clamp(0., 1.),
+            monotonic: file_config.adaptive.monotonic.unwrap_or(false),
+            confirm: file_config.adaptive.confirm.unwrap_or(false),
+        },
+
+        cooldown: app::Cooldown {
+            adjustment: Duration::from_millis(
+                file_config
+                    .cooldown
+                    .adjustment_ms
+                    .unwrap_or(DEFAULT_ADJUSTMENT_COOLDOWN_MS),
+            ),
+            als: Duration::from_millis(
+                file_config
+                    .cooldown
+                    .als_ms
+                    .unwrap_or(DEFAULT_ALS_COOLDOWN_MS),
+            ),
+        },
+
+        apps: app::Apps {
+            separate_data: file_config.apps.separate_data.unwrap_or(false),
+            disabled: file_config.apps.disabled.unwrap_or_default(),
+        },
+
         als: match file_config.als {
             file::Als::Iio { path, thresholds } => app::Als::Iio {
                 path,
@@ -121,12 +285,48 @@ fn validate(config: app::Config) -> Result<app::Config, Box<dyn Error>> {
         .map(|output| match output {
             app::Output::Backlight(app::BacklightOutput { name, .. }) => name,
             app::Output::DdcUtil(DdcUtilOutput { name, .. }) => name,
+            app::Output::Gamma(GammaOutput { name, .. }) => name,
+            app::Output::Command(CommandOutput { name, .. }) => name,
         })
         .collect::<HashSet<_>>();
 
     match (names.len(), names.len() == config.output.len()) {
-        (0, _) => Err("No output or keyboard configured".into()),
-        (_, false) => Err("Names of all outputs and keyboards are not unique".into()),
-        _ => Ok(config),
+        (0, _) => Err("No output or keyboard configured")?,
+        (_, false) => Err("Names of all outputs and keyboards are not unique")?,
+        _ => {}
     }
+
+    let follows = config
+        .output
+        .iter()
+        .filter_map(|output| match output {
+            app::Output::Backlight(app::BacklightOutput { name, follow, .. }) => {
+                Some((name, follow.as_ref()?))
+            }
+            app::Output::DdcUtil(DdcUtilOutput { name, follow, .. }) => {
+                Some((name, follow.as_ref()?))
+            }
+            app::Output::Gamma(GammaOutput { name, follow, .. }) => Some((name, follow.as_ref()?)),
+            app::Output::Command(CommandOutput { name, follow, .. }) => {
+                Some((name, follow.as_ref()?))
+            }
+        })
+        .collect::<HashMap<_, _>>();
+
+    for (name, follow) in &follows {
+        if !names.contains(&follow.leader) {
+            Err(format!(
+                "Output '{}' follows '{}', which is not configured",
+                name, follow.leader
+            ))?;
+        }
+        if follows.contains_key(&follow.leader) {
+            Err(format!(
+                "Output '{}' follows '{}', which is itself a follower",
+                name, follow.leader
+            ))?;
+        }
+    }
+
+    Ok(config)
 }
@@ -0,0 +1,301 @@
+use crate::brightness::Event;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// How long the daemon waits for a client to send its command.
+const REQUEST_TIMEOUT_SECS: u64 = 1;
+
+pub const USAGE: &str = "\
+Usage:
+  wluma                                     run the daemon
+  wluma hold [--output <name>] [<minutes>]  pause predictions and learning
+  wluma release [--output <name>]           cancel the hold
+  wluma data <command>                      manage learned data, see `wluma data help`";
+
+/// Command sent to a running daemon, applies to all outputs unless a specific one is given.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Hold {
+        output: Option<String>,
+        minutes: Option<u64>,
+    },
+    Release {
+        output: Option<String>,
+    },
+}
+
+impl Command {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let (name, args) = args.split_first().ok_or("Missing command")?;
+
+        let mut output = None;
+        let mut positional = vec![];
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--output" => output = Some(args.next().ok_or("Missing output name")?.clone()),
+                _ => positional.push(arg),
+            }
+        }
+
+        match (name.as_str(), positional.as_slice()) {
+            ("hold", []) => Ok(Self::Hold {
+                output,
+                minutes: None,
+            }),
+            ("hold", [minutes]) => Ok(Self::Hold {
+                output,
+                minutes: Some(
+                    minutes
+                        .parse()
+                        .map_err(|_| format!("Invalid number of minutes: {}", minutes))?,
+                ),
+            }),
+            ("release", []) => Ok(Self::Release { output }),
+            _ => Err(format!(
+                "Invalid command: {}",
+                args_to_string(name, &positional)
+            )),
+        }
+    }
+
+    fn to_args(&self) -> Vec<String> {
+        let (name, output, minutes) = match self {
+            Self::Hold { output, minutes } => ("hold", output, *minutes),
+            Self::Release { output } => ("release", output, None),
+        };
+
+        std::iter::once(name.to_string())
+            .chain(
+                output
+                    .iter()
+                    .flat_map(|output| ["--output".to_string(), output.clone()]),
+            )
+            .chain(minutes.map(|minutes| minutes.to_string()))
+            .collect()
+    }
+}
+
+fn args_to_string(name: &str, args: &[&String]) -> String {
+    std::iter::once(name)
+        .chain(args.iter().map(|arg| arg.as_str()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn socket_path() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("wluma.sock")
+}
+
+/// Sends the command to a running daemon and returns its response.
+pub fn send(command: &Command) -> Result<String, Box<dyn Error>> {
+    let mut stream = UnixStream::connect(socket_path())
+        .map_err(|err| format!("Unable to connect to wluma, is it running? ({})", err))?;
+
+    // Arguments are separated by NUL, as output names may contain spaces
+    stream.write_all(format!("{}\n", command.to_args().join("\0")).as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    match response.strip_prefix("error: ") {
+        Some(err) => Err(err.trim().into()),
+        None => Ok(response.trim().to_string()),
+    }
+}
+
+pub struct Server {
+    listener: UnixListener,
+    event_txs: HashMap<String, Sender<Event>>,
+    default_hold: Duration,
+}
+
+impl Server {
+    pub fn new(
+        event_txs: HashMap<String, Sender<Event>>,
+        default_hold: Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        let path = socket_path();
+
+        // A leftover socket from a previous run that nobody listens on anymore
+        if path.exists() && UnixStream::connect(&path).is_err() {
+            fs::remove_file(&path)?;
+        }
+
+        Ok(Self {
+            listener: UnixListener::bind(&path)?,
+            event_txs,
+            default_hold,
+        })
+    }
+
+    pub fn run(&self) {
+        for stream in self.listener.incoming() {
+            let result = stream
+                .map_err(|err| err.into())
+                .and_then(|mut stream| self.handle(&mut stream));
+
+            if let Err(err) = result {
+                log::warn!("Unable to handle control command: {}", err);
+            }
+        }
+    }
+
+    fn handle(&self, stream: &mut UnixStream) -> Result<(), Box<dyn Error>> {
+        // A client that never finishes its command must not block the following ones
+        stream.set_read_timeout(Some(Duration::from_secs(REQUEST_TIMEOUT_SECS)))?;
+        let mut request = String::new();
+        BufReader::new(&*stream).read_line(&mut request)?;
+
+        let args = request
+            .trim_end_matches('\n')
+            .split('\0')
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let response = match Command::parse(&args).and_then(|command| self.apply(command)) {
+            Ok(response) => response,
+            Err(err) => format!("error: {}", err),
+        };
+
+        stream.write_all(format!("{}\n", response).as_bytes())?;
+        Ok(())
+    }
+
+    fn apply(&self, command: Command) -> Result<String, String> {
+        let (output, event, response) = match command {
+            Command::Hold { output, minutes } => {
+                let duration = minutes
+                    .map(|minutes| Duration::from_secs(minutes * 60))
+                    .unwrap_or(self.default_hold);
+                let response = format!("Brightness is on hold for {} min", duration.as_secs() / 60);
+                (output, Event::Hold(duration), response)
+            }
+            Command::Release { output } => (
+                output,
+                Event::Release,
+                "Brightness is no longer on hold".to_string(),
+            ),
+        };
+
+        let event_txs = match &output {
+            Some(name) => vec![self
+                .event_txs
+                .get(name)
+                .ok_or_else(|| format!("Unknown output: {}", name))?],
+            None => self.event_txs.values().collect(),
+        };
+
+        for event_tx in event_txs {
+            event_tx
+                .send(event)
+                .map_err(|_| "Output is no longer running".to_string())?;
+        }
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use std::sync::mpsc;
+
+    fn server(name: &str, event_txs: HashMap<String, Sender<Event>>) -> (Server, TempDir) {
+        let dir = TempDir::new(&format!("control-{}", name));
+        let server = Server {
+            listener: UnixListener::bind(dir.join("wluma.sock")).unwrap(),
+            event_txs,
+            default_hold: Duration::from_secs(60),
+        };
+        (server, dir)
+    }
+
+    fn args(args: &str) -> Vec<String> {
+        args.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_hold() {
+        assert_eq!(
+            Ok(Command::Hold {
+                output: None,
+                minutes: None
+            }),
+            Command::parse(&args("hold"))
+        );
+        assert_eq!(
+            Ok(Command::Hold {
+                output: Some("eDP-1".to_string()),
+                minutes: Some(15)
+            }),
+            Command::parse(&args("hold --output eDP-1 15"))
+        );
+        assert!(Command::parse(&args("hold soon")).is_err());
+        assert!(Command::parse(&args("hold --output")).is_err());
+    }
+
+    #[test]
+    fn test_parse_release() {
+        assert_eq!(
+            Ok(Command::Release {
+                output: Some("eDP-1".to_string()),
+            }),
+            Command::parse(&args("release --output eDP-1"))
+        );
+        assert!(Command::parse(&args("release 5")).is_err());
+        assert!(Command::parse(&args("unknown")).is_err());
+    }
+
+    #[test]
+    fn test_args_roundtrip() {
+        let command = || Command::Hold {
+            output: Some("Dell Inc. DELL P2415Q".to_string()),
+            minutes: Some(30),
+        };
+
+        assert_eq!(Ok(command()), Command::parse(&command().to_args()));
+    }
+
+    #[test]
+    fn test_handle_reads_single_line() -> Result<(), Box<dyn Error>> {
+        let (event_tx, event_rx) = mpsc::channel();
+        let (server, _dir) = server("line", HashMap::from([("eDP-1".to_string(), event_tx)]));
+        let (mut client, mut stream) = UnixStream::pair()?;
+
+        // client keeps its write side open while waiting for the response
+        let command = Command::Hold {
+            output: Some("eDP-1".to_string()),
+            minutes: Some(5),
+        };
+        client.write_all(format!("{}\n", command.to_args().join("\0")).as_bytes())?;
+        server.handle(&mut stream)?;
+
+        assert_eq!(Event::Hold(Duration::from_secs(300)), event_rx.try_recv()?);
+        let mut response = String::new();
+        BufReader::new(&client).read_line(&mut response)?;
+        assert_eq!("Brightness is on hold for 5 min\n", response);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_handle_gives_up_on_silent_client() -> Result<(), Box<dyn Error>> {
+        let (server, _dir) = server("silent", HashMap::new());
+        let (_client, mut stream) = UnixStream::pair()?;
+
+        assert!(server.handle(&mut stream).is_err());
+
+        Ok(())
+    }
+}
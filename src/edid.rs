@@ -0,0 +1,111 @@
+use std::fs;
+
+pub const DRM_CLASS_PATH: &str = "/sys/class/drm";
+
+const HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+const DESCRIPTORS: [usize; 4] = [54, 72, 90, 108];
+const DESCRIPTOR_NAME: u8 = 0xFC;
+const DESCRIPTOR_SERIAL: u8 = 0xFF;
+
+/// Stable identity of the monitor connected to the given DRM connector (e.g. `DP-1`),
+/// made of its manufacturer, model and serial number, so that it doesn't change between docks.
+pub fn identity(connector: &str) -> Option<String> {
+    let suffix = format!("-{}", connector);
+    fs::read_dir(DRM_CLASS_PATH)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("card") && name.ends_with(&suffix)
+        })
+        .find_map(|entry| parse(&fs::read(entry.path().join("edid")).ok()?))
+}
+
+fn parse(edid: &[u8]) -> Option<String> {
+    if edid.len() < 128 || edid[..8] != HEADER {
+        return None;
+    }
+
+    // Three letters of five bits each, where 1 is 'A'
+    let id = u16::from_be_bytes([edid[8], edid[9]]);
+    let manufacturer = [10, 5, 0]
+        .iter()
+        .map(|shift| (b'A' - 1 + ((id >> shift) & 0x1F) as u8) as char)
+        .collect::<String>();
+
+    let text = |tag: u8| {
+        DESCRIPTORS.iter().find_map(|&offset| {
+            let descriptor = &edid[offset..offset + 18];
+            (descriptor[..3] == [0, 0, 0] && descriptor[3] == tag).then(|| {
+                let text = &descriptor[5..];
+                let end = text.iter().position(|&c| c == b'\n').unwrap_or(text.len());
+                String::from_utf8_lossy(&text[..end]).trim().to_string()
+            })
+        })
+    };
+
+    let model = text(DESCRIPTOR_NAME)
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| format!("{:04X}", u16::from_le_bytes([edid[10], edid[11]])));
+
+    let serial = text(DESCRIPTOR_SERIAL)
+        .filter(|serial| !serial.is_empty())
+        .or_else(|| {
+            let serial = u32::from_le_bytes([edid[12], edid[13], edid[14], edid[15]]);
+            (serial != 0).then(|| serial.to_string())
+        });
+
+    Some(
+        [Some(manufacturer), Some(model), serial]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edid(descriptors: &[(u8, &str)], serial: u32) -> Vec<u8> {
+        let mut edid = vec![0; 128];
+        edid[..8].copy_from_slice(&HEADER);
+        // "DEL"
+        edid[8..10].copy_from_slice(&0x10AC_u16.to_be_bytes());
+        edid[10..12].copy_from_slice(&0xA0C7_u16.to_le_bytes());
+        edid[12..16].copy_from_slice(&serial.to_le_bytes());
+
+        for (&offset, (tag, text)) in DESCRIPTORS.iter().zip(descriptors) {
+            edid[offset + 3] = *tag;
+            let mut text = format!("{}\n", text).into_bytes();
+            text.resize(13, b' ');
+            edid[offset + 5..offset + 18].copy_from_slice(&text);
+        }
+        edid
+    }
+
+    #[test]
+    fn test_parse_name_and_serial() {
+        let edid = edid(
+            &[
+                (DESCRIPTOR_SERIAL, "7FH7K65I0Z2L"),
+                (DESCRIPTOR_NAME, "DELL P2415Q"),
+            ],
+            12345,
+        );
+
+        assert_eq!(
+            Some("DEL DELL P2415Q 7FH7K65I0Z2L".to_string()),
+            parse(&edid)
+        );
+    }
+
+    #[test]
+    fn test_parse_without_descriptors() {
+        assert_eq!(Some("DEL A0C7 12345".to_string()), parse(&edid(&[], 12345)));
+        assert_eq!(Some("DEL A0C7".to_string()), parse(&edid(&[], 0)));
+        assert_eq!(None, parse(&[0; 128]));
+    }
+}
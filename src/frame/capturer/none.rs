@@ -1,13 +1,21 @@
+use crate::power;
 use std::{thread, time::Duration};
 
-#[derive(Default)]
-pub struct Capturer {}
+pub struct Capturer {
+    power: power::Listener,
+}
+
+impl Capturer {
+    pub fn new(power: power::Listener) -> Self {
+        Self { power }
+    }
+}
 
 impl super::Capturer for Capturer {
     fn run(&mut self, _output_name: &str, mut controller: Box<dyn crate::predictor::Controller>) {
         loop {
             controller.adjust(0);
-            thread::sleep(Duration::from_millis(200));
+            thread::sleep(Duration::from_millis(200) * self.power.state().slowdown);
         }
     }
 }
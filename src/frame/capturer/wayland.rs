@@ -1,10 +1,16 @@
-use crate::config::WaylandProtocol;
+use crate::brightness::Event;
+use crate::config::{Apps, WaylandProtocol};
 use crate::frame::object::Object;
 use crate::frame::vulkan::Vulkan;
 use crate::predictor::Controller;
+use crate::power;
+use std::collections::HashMap;
 use std::os::fd::BorrowedFd;
+use std::sync::mpsc::Sender;
 use std::thread;
 use std::time::Duration;
+use wayland_client::backend::ObjectId;
+use wayland_client::event_created_child;
 use wayland_client::protocol::wl_buffer::WlBuffer;
 use wayland_client::protocol::wl_output::WlOutput;
 use wayland_client::protocol::wl_registry::WlRegistry;
@@ -23,6 +29,8 @@ use wayland_protocols::wp::linux_dmabuf::zv1::client::zwp_linux_buffer_params_v1
 use wayland_protocols::wp::linux_dmabuf::zv1::client::zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1;
 use wayland_protocols_wlr::export_dmabuf::v1::client::zwlr_export_dmabuf_frame_v1::ZwlrExportDmabufFrameV1;
 use wayland_protocols_wlr::export_dmabuf::v1::client::zwlr_export_dmabuf_manager_v1::ZwlrExportDmabufManagerV1;
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1;
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1;
 use wayland_protocols_wlr::screencopy::v1::client::zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1;
 use wayland_protocols_wlr::screencopy::v1::client::zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1;
 
@@ -35,8 +43,15 @@ pub struct Capturer {
     vulkan: Option<Vulkan>,
     output: Option<WlOutput>,
     output_global_id: Option<u32>,
+    /// Output that the predictor was last told the identity of.
+    identified_global_id: Option<u32>,
+    outputs: HashMap<u32, OutputInfo>,
     pending_frame: Option<Object>,
     controller: Option<Box<dyn Controller>>,
+    apps: Apps,
+    event_tx: Sender<Event>,
+    focused_app: Option<String>,
+    power: power::Listener,
     // linux-dmabuf-v1
     dmabuf: Option<ZwpLinuxDmabufV1>,
     wl_buffer: Option<WlBuffer>,
@@ -49,6 +64,27 @@ pub struct Capturer {
     screencopy_manager: Option<ZwlrScreencopyManagerV1>,
     // wlr-export-dmabuf-unstable-v1
     dmabuf_manager: Option<ZwlrExportDmabufManagerV1>,
+    // wlr-foreign-toplevel-management-unstable-v1
+    toplevel_manager: Option<ZwlrForeignToplevelManagerV1>,
+    toplevels: HashMap<ObjectId, Toplevel>,
+    focus_sequence: u64,
+}
+
+/// What the compositor tells about each output, to identify the monitor connected to it.
+#[derive(Default)]
+struct OutputInfo {
+    connector: Option<String>,
+    make_model: Option<String>,
+}
+
+/// What the compositor tells about each opened app window, to know which app is focused on the output.
+#[derive(Default)]
+struct Toplevel {
+    app_id: Option<String>,
+    activated: bool,
+    outputs: Vec<WlOutput>,
+    /// When the window was focused last, in order of focus changes, or 0 if never.
+    focused_at: u64,
 }
 
 #[derive(Clone)]
@@ -58,15 +94,26 @@ struct GlobalsContext {
 }
 
 impl Capturer {
-    pub fn new(protocol: WaylandProtocol) -> Self {
+    pub fn new(
+        protocol: WaylandProtocol,
+        apps: Apps,
+        event_tx: Sender<Event>,
+        power: power::Listener,
+    ) -> Self {
         Self {
             protocol,
             is_processing_frame: false,
             vulkan: None,
             output: None,
             output_global_id: None,
+            identified_global_id: None,
+            outputs: HashMap::new(),
             pending_frame: None,
             controller: None,
+            apps,
+            event_tx,
+            focused_app: None,
+            power,
             // linux-dmabuf-v1
             dmabuf: None,
             wl_buffer: None,
@@ -79,6 +126,10 @@ impl Capturer {
             screencopy_manager: None,
             // wlr-export-dmabuf-unstable-v1
             dmabuf_manager: None,
+            // wlr-foreign-toplevel-management-unstable-v1
+            toplevel_manager: None,
+            toplevels: HashMap::new(),
+            focus_sequence: 0,
         }
     }
 }
@@ -153,8 +204,13 @@ impl super::Capturer for Capturer {
         };
         log::debug!("Using {protocol_to_use} protocol to request frames");
 
+        if self.tracks_focus() && self.toplevel_manager.is_none() {
+            log::warn!("Per-app settings require wlr-foreign-toplevel-management-unstable-v1 protocol, but it's not available");
+        }
+
         self.vulkan = Some(Vulkan::new().expect("Unable to initialize Vulkan"));
         self.controller = Some(controller);
+        self.update_identity();
 
         loop {
             if !self.is_processing_frame {
@@ -219,10 +275,87 @@ impl super::Capturer for Capturer {
             event_queue
                 .blocking_dispatch(self)
                 .expect("Error running wayland capturer main loop");
+
+            self.update_identity();
+            self.update_focus();
         }
     }
 }
 
+impl Capturer {
+    /// Identity of the monitor from its EDID, or at least its make and model.
+    fn identity(&self) -> Option<String> {
+        let info = self.outputs.get(&self.output_global_id?)?;
+        info.connector
+            .as_deref()
+            .and_then(crate::edid::identity)
+            .or_else(|| info.make_model.clone())
+    }
+
+    /// Lets predictor know which monitor it is whenever the output gets connected,
+    /// e.g. after moving to another dock, so that it keeps learning for the right monitor.
+    fn update_identity(&mut self) {
+        if self.output_global_id.is_none() || self.output_global_id == self.identified_global_id {
+            return;
+        }
+        self.identified_global_id = self.output_global_id;
+
+        let identity = self.identity();
+        log::debug!("Identified connected monitor as {:?}", identity);
+        if let Some(controller) = self.controller.as_mut() {
+            controller.identify(identity.as_deref());
+        }
+    }
+
+    fn tracks_focus(&self) -> bool {
+        self.apps.separate_data || !self.apps.disabled.is_empty()
+    }
+
+    /// App that was focused last on the output.
+    fn focused_app(&self) -> Option<String> {
+        let output = self.output.as_ref()?;
+        self.toplevels
+            .values()
+            .filter(|toplevel| toplevel.focused_at > 0 && toplevel.outputs.contains(output))
+            .max_by_key(|toplevel| toplevel.focused_at)
+            .and_then(|toplevel| toplevel.app_id.clone())
+    }
+
+    /// Pauses automation while an app that disables it is focused,
+    /// and lets predictor learn preferences per app, if enabled.
+    fn update_focus(&mut self) {
+        let app = self.focused_app();
+        if app == self.focused_app {
+            return;
+        }
+
+        let disabled = |app: &Option<String>| {
+            app.as_ref()
+                .is_some_and(|app| self.apps.disabled.contains(app))
+        };
+        let (was_disabled, is_disabled) = (disabled(&self.focused_app), disabled(&app));
+        if was_disabled != is_disabled {
+            match &app {
+                Some(app) if is_disabled => log::info!(
+                    "Brightness will be neither predicted nor learned while '{}' is focused",
+                    app
+                ),
+                _ => log::info!("Brightness is no longer paused by the focused app"),
+            }
+            self.event_tx
+                .send(Event::Disabled(is_disabled))
+                .expect("Unable to send focused app event, channel is dead");
+        }
+
+        if self.apps.separate_data {
+            if let Some(controller) = self.controller.as_mut() {
+                controller.focus(app.as_deref());
+            }
+        }
+        self.focused_app = app;
+    }
+}
+
 // ==== Globals ====
 
 impl Dispatch<WlOutput, GlobalsContext> for Capturer {
@@ -236,7 +369,22 @@ impl Dispatch<WlOutput, GlobalsContext> for Capturer {
     ) {
         use wayland_client::protocol::wl_output::Event;
 
+        let info = ctx
+            .global_id
+            .map(|global_id| state.outputs.entry(global_id).or_default());
+
         match event {
+            Event::Name { name } => {
+                if let Some(info) = info {
+                    info.connector = Some(name);
+                }
+            }
+            Event::Geometry { make, model, .. } => {
+                let known = |s: &str| !s.is_empty() && s != "Unknown";
+                if let Some(info) = info.filter(|_| known(&make) && known(&model)) {
+                    info.make_model = Some(format!("{} {}", make, model));
+                }
+            }
             Event::Description { description } if description.contains(&ctx.desired_output) => {
                 if state.output.is_none() {
                     log::debug!(
@@ -312,6 +460,18 @@ impl Dispatch<WlRegistry, GlobalsContext> for Capturer {
                                 (),
                             ));
                     }
+                    _ if interface == ZwlrForeignToplevelManagerV1::interface().name
+                        && state.tracks_focus() =>
+                    {
+                        log::debug!("Detected support for wlr-foreign-toplevel-management-unstable-v1 protocol");
+                        state.toplevel_manager =
+                            Some(registry.bind::<ZwlrForeignToplevelManagerV1, _, _>(
+                                name,
+                                version.min(3),
+                                qh,
+                                (),
+                            ));
+                    }
                     _ if interface == ExtImageCopyCaptureManagerV1::interface().name => {
                         log::debug!("Detected support for ext-image-copy-capture-v1 protocol");
                         state.img_copy_capture_manager =
@@ -338,6 +498,74 @@ impl Dispatch<WlRegistry, GlobalsContext> for Capturer {
     }
 }
 
+// ==== wlr-foreign-toplevel-management-unstable-v1 protocol ====
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for Capturer {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: <ZwlrForeignToplevelManagerV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::Event;
+
+        if let Event::Toplevel { toplevel } = event {
+            state.toplevels.insert(toplevel.id(), Toplevel::default());
+        }
+    }
+
+    event_created_child!(Capturer, ZwlrForeignToplevelManagerV1, [
+        wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for Capturer {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: <ZwlrForeignToplevelHandleV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_handle_v1::{Event, State};
+
+        let Some(toplevel) = state.toplevels.get_mut(&handle.id()) else {
+            return;
+        };
+
+        match event {
+            Event::AppId { app_id } => toplevel.app_id = Some(app_id),
+
+            Event::State { state: states } => {
+                let activated = states
+                    .chunks_exact(4)
+                    .map(|s| u32::from_ne_bytes([s[0], s[1], s[2], s[3]]))
+                    .any(|s| s == State::Activated as u32);
+
+                if activated && !toplevel.activated {
+                    state.focus_sequence += 1;
+                    toplevel.focused_at = state.focus_sequence;
+                }
+                toplevel.activated = activated;
+            }
+
+            Event::OutputEnter { output } => toplevel.outputs.push(output),
+
+            Event::OutputLeave { output } => toplevel.outputs.retain(|o| *o != output),
+
+            Event::Closed => {
+                state.toplevels.remove(&handle.id());
+                handle.destroy();
+            }
+
+            _ => {}
+        }
+    }
+}
+
 // ==== wlr-export-dmabuf-unstable-v1 protocol ====
 
 impl Dispatch<ZwlrExportDmabufManagerV1, ()> for Capturer {
@@ -396,7 +624,7 @@ impl Dispatch<ZwlrExportDmabufFrameV1, ()> for Capturer {
 
                 frame.destroy();
 
-                thread::sleep(DELAY_SUCCESS);
+                thread::sleep(DELAY_SUCCESS * state.power.state().slowdown);
                 state.is_processing_frame = false;
             }
 
@@ -543,7 +771,7 @@ impl Dispatch<ZwlrScreencopyFrameV1, ()> for Capturer {
 
                 frame.destroy();
 
-                thread::sleep(DELAY_SUCCESS);
+                thread::sleep(DELAY_SUCCESS * state.power.state().slowdown);
                 state.is_processing_frame = false;
             }
 
@@ -706,7 +934,7 @@ impl Dispatch<ExtImageCopyCaptureFrameV1, ()> for Capturer {
 
                 frame.destroy();
 
-                thread::sleep(DELAY_SUCCESS);
+                thread::sleep(DELAY_SUCCESS * state.power.state().slowdown);
                 state.is_processing_frame = false;
             }
 
@@ -722,3 +950,72 @@ impl Dispatch<ExtImageCopyCaptureFrameV1, ()> for Capturer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+
+    /// Controller that only records the identities it is told.
+    struct Identities(Rc<RefCell<Vec<Option<String>>>>);
+
+    impl Controller for Identities {
+        fn adjust(&mut self, _luma: u8) {}
+
+        fn identify(&mut self, identity: Option<&str>) {
+            self.0.borrow_mut().push(identity.map(str::to_string));
+        }
+    }
+
+    fn connect(capturer: &mut Capturer, global_id: u32, make_model: Option<&str>) {
+        capturer.outputs.insert(
+            global_id,
+            OutputInfo {
+                connector: None,
+                make_model: make_model.map(str::to_string),
+            },
+        );
+        capturer.output_global_id = Some(global_id);
+    }
+
+    #[test]
+    fn test_identifies_monitor_again_when_output_is_swapped() {
+        let identities = Rc::new(RefCell::new(vec![]));
+        let (event_tx, _) = mpsc::channel();
+        let mut capturer = Capturer::new(
+            WaylandProtocol::Any,
+            Apps::default(),
+            event_tx,
+            power::Listener::new(mpsc::channel().1),
+        );
+        capturer.controller = Some(Box::new(Identities(identities.clone())));
+
+        // Output is not connected at startup
+        capturer.update_identity();
+        assert!(identities.borrow().is_empty());
+
+        connect(&mut capturer, 40, Some("Dell P2415Q"));
+        capturer.update_identity();
+        capturer.update_identity();
+
+        // Monitor is disconnected and another one is connected to another dock
+        capturer.output_global_id = None;
+        capturer.update_identity();
+        connect(&mut capturer, 41, Some("Dell U2720Q"));
+        capturer.update_identity();
+
+        connect(&mut capturer, 42, None);
+        capturer.update_identity();
+
+        assert_eq!(
+            vec![
+                Some("Dell P2415Q".to_string()),
+                Some("Dell U2720Q".to_string()),
+                None
+            ],
+            *identities.borrow()
+        );
+    }
+}
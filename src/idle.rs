@@ -0,0 +1,155 @@
+use crate::brightness;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use wayland_client::protocol::wl_registry::WlRegistry;
+use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_client::{Connection, Dispatch, Proxy, QueueHandle};
+use wayland_protocols::ext::idle_notify::v1::client::ext_idle_notification_v1::ExtIdleNotificationV1;
+use wayland_protocols::ext::idle_notify::v1::client::ext_idle_notifier_v1::ExtIdleNotifierV1;
+
+/// Notifies each output when the user has been idle for its timeout,
+/// and as soon as they are active again. All outputs share one Wayland connection.
+pub struct Idle {
+    timeouts: Vec<(Duration, Sender<brightness::Event>)>,
+    seat: Option<WlSeat>,
+    notifier: Option<ExtIdleNotifierV1>,
+}
+
+impl Idle {
+    pub fn new(timeouts: Vec<(Duration, Sender<brightness::Event>)>) -> Self {
+        Self {
+            timeouts,
+            seat: None,
+            notifier: None,
+        }
+    }
+
+    /// Idle timeout is not essential, so it is given up on errors rather than taking brightness control down.
+    pub fn run(&mut self) {
+        let connection = match Connection::connect_to_env() {
+            Ok(connection) => connection,
+            Err(err) => {
+                log::error!(
+                    "Unable to connect to Wayland display, idle timeout will be ignored: {err}"
+                );
+                return;
+            }
+        };
+        let mut event_queue = connection.new_event_queue();
+        let qh = event_queue.handle();
+
+        connection.display().get_registry(&qh, ());
+
+        if let Err(err) = event_queue.roundtrip(self) {
+            log::error!("Unable to perform initial roundtrip, idle timeout will be ignored: {err}");
+            return;
+        }
+
+        let (seat, notifier) = match (&self.seat, &self.notifier) {
+            (Some(seat), Some(notifier)) => (seat, notifier),
+            _ => {
+                log::warn!("Compositor does not support ext-idle-notify-v1 protocol, idle timeout will be ignored");
+                return;
+            }
+        };
+
+        for (index, (timeout, _)) in self.timeouts.iter().enumerate() {
+            notifier.get_idle_notification(timeout.as_millis() as u32, seat, &qh, index);
+        }
+
+        loop {
+            if let Err(err) = event_queue.blocking_dispatch(self) {
+                log::error!(
+                    "Error running idle notifier main loop, idle timeout will be ignored: {err}"
+                );
+                return;
+            }
+        }
+    }
+}
+
+impl Dispatch<WlRegistry, ()> for Idle {
+    fn event(
+        state: &mut Self,
+        registry: &WlRegistry,
+        event: <WlRegistry as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        use wayland_client::protocol::wl_registry::Event;
+
+        if let Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            match &interface[..] {
+                // Any seat will do, as we are interested in overall user activity
+                _ if interface == WlSeat::interface().name && state.seat.is_none() => {
+                    state.seat = Some(registry.bind::<WlSeat, _, _>(name, version, qh, ()));
+                }
+                _ if interface == ExtIdleNotifierV1::interface().name => {
+                    log::debug!("Detected support for ext-idle-notify-v1 protocol");
+                    state.notifier =
+                        Some(registry.bind::<ExtIdleNotifierV1, _, _>(name, version, qh, ()));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Dispatch<WlSeat, ()> for Idle {
+    fn event(
+        _: &mut Self,
+        _: &WlSeat,
+        _: <WlSeat as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtIdleNotifierV1, ()> for Idle {
+    fn event(
+        _: &mut Self,
+        _: &ExtIdleNotifierV1,
+        _: <ExtIdleNotifierV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, usize> for Idle {
+    fn event(
+        state: &mut Self,
+        _: &ExtIdleNotificationV1,
+        event: <ExtIdleNotificationV1 as Proxy>::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use wayland_protocols::ext::idle_notify::v1::client::ext_idle_notification_v1::Event;
+
+        let idle = match event {
+            Event::Idled => true,
+            Event::Resumed => false,
+            _ => return,
+        };
+
+        let (timeout, event_tx) = &state.timeouts[*index];
+        log::debug!(
+            "User is {} (timeout: {:?})",
+            if idle { "idle" } else { "active again" },
+            timeout
+        );
+        if event_tx.send(brightness::Event::Idle(idle)).is_err() {
+            log::warn!("Unable to send idle status, channel is dead");
+        }
+    }
+}
@@ -1,12 +1,22 @@
 use itertools::Itertools;
-use std::sync::mpsc;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
 
 mod als;
 mod brightness;
 mod config;
+mod control;
 mod device_file;
+mod edid;
 mod frame;
+mod idle;
+mod power;
 mod predictor;
+mod session;
+#[cfg(test)]
+mod temp_dir;
 
 /// Current app version (determined at compile-time).
 pub const VERSION: &str = env!("WLUMA_VERSION");
@@ -25,6 +35,25 @@ fn main() {
 
     log::debug!("== wluma v{} ==", VERSION);
 
+    let args = std::env::args().skip(1).collect_vec();
+    if !args.is_empty() {
+        let result = match args[0].as_str() {
+            "data" => predictor::cli::run(&args[1..]),
+            _ => control::Command::parse(&args)
+                .map_err(|err| format!("{}\n\n{}", err, control::USAGE).into())
+                .and_then(|command| control::send(&command)),
+        };
+
+        match result {
+            Ok(response) => println!("{}", response),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     let config = match config::load() {
         Ok(config) => config,
         Err(err) => panic!("Unable to load config: {}", err),
@@ -32,46 +61,152 @@ fn main() {
 
     log::debug!("Using {:#?}", config);
 
-    let als_txs = config
+    let als_profiles = als::configured_profiles(&config.als);
+
+    let (follower_outputs, leader_outputs): (Vec<_>, Vec<_>) = config
         .output
         .iter()
-        .filter_map(|output| {
+        .partition(|output| output_follow(output).is_some());
+
+    // Followers are started first, so that their leaders know where to forward brightness changes.
+    // Followers of a leader that fails to start are kept here, so that their channels stay alive.
+    let mut followers = HashMap::<String, Vec<brightness::Follower>>::new();
+    let mut als_txs = vec![];
+    let mut event_txs = HashMap::new();
+    let mut idle_timeouts = vec![];
+    let mut power_txs = vec![];
+
+    for output in follower_outputs {
+        let follow = output_follow(output).expect("Output must be a follower");
+        let output_name = output_name(output);
+
+        match create_brightness(output, &mut als_txs) {
+            Ok(b) => {
+                let ignore = config.ignore.clone();
+                let (user_tx, user_rx) = mpsc::channel();
+                let (prediction_tx, prediction_rx) = mpsc::channel();
+                let (event_tx, event_rx) = mpsc::channel();
+                event_txs.insert(output_name.to_string(), event_tx.clone());
+
+                let idle_dim = match output_idle(output) {
+                    Some(idle) => {
+                        idle_timeouts.push((idle.timeout, event_tx));
+                        idle.dim
+                    }
+                    None => 100,
+                };
+
+                followers.entry(follow.leader.clone()).or_default().push(
+                    brightness::Follower::new(
+                        prediction_tx,
+                        user_rx,
+                        brightness::Curve::new(&follow.curve),
+                        b.max_brightness(),
+                    ),
+                );
+
+                let thread_name = format!("backlight-{}", output_name);
+                std::thread::Builder::new()
+                    .name(thread_name.clone())
+                    .spawn(move || {
+                        brightness::Controller::new(
+                            b,
+                            user_tx,
+                            prediction_rx,
+                            event_rx,
+                            idle_dim,
+                            vec![],
+                            ignore,
+                        )
+                        .run();
+                    })
+                    .unwrap_or_else(|_| panic!("Unable to start thread: {}", thread_name));
+            }
+            Err(err) => log::warn!(
+                "Skipping '{}' as it might be disconnected: {}",
+                output_name,
+                err
+            ),
+        }
+    }
+
+    let leader_als_txs = leader_outputs
+        .into_iter()
+        .flat_map(|output| {
+            let event_txs = &mut event_txs;
+            let idle_timeouts = &mut idle_timeouts;
+            let power_txs = &mut power_txs;
             let output_clone = output.clone();
 
             let (als_tx, als_rx) = mpsc::channel();
+            let mut output_als_txs = vec![als_tx];
             let (user_tx, user_rx) = mpsc::channel();
             let (prediction_tx, prediction_rx) = mpsc::channel();
 
             let (output_name, output_capturer) = match output_clone.clone() {
                 config::Output::Backlight(cfg) => (cfg.name, cfg.capturer),
                 config::Output::DdcUtil(cfg) => (cfg.name, cfg.capturer),
+                config::Output::Gamma(cfg) => (cfg.name, cfg.capturer),
+                config::Output::Command(cfg) => (cfg.name, cfg.capturer),
             };
 
-            let brightness = match output {
-                config::Output::Backlight(cfg) => {
-                    brightness::Backlight::new(&cfg.path, cfg.min_brightness)
-                        .map(|b| Box::new(b) as Box<dyn brightness::Brightness + Send>)
-                }
-                config::Output::DdcUtil(cfg) => {
-                    brightness::DdcUtil::new(&cfg.name, cfg.min_brightness)
-                        .map(|b| Box::new(b) as Box<dyn brightness::Brightness + Send>)
-                }
-            };
+            let brightness = create_brightness(output, &mut output_als_txs);
 
             match brightness {
                 Ok(b) => {
+                    let (event_tx, event_rx) = mpsc::channel();
+                    event_txs.insert(output_name.clone(), event_tx.clone());
+                    let focus_tx = event_tx.clone();
+
+                    let idle_dim = match output_idle(output) {
+                        Some(idle) => {
+                            idle_timeouts.push((idle.timeout, event_tx));
+                            idle.dim
+                        }
+                        None => 100,
+                    };
+
+                    let max_brightness = b.max_brightness();
+                    let output_followers = followers.remove(&output_name).unwrap_or_default();
+                    let ignore = config.ignore.clone();
+
                     let thread_name = format!("backlight-{}", output_name);
                     std::thread::Builder::new()
                         .name(thread_name.clone())
                         .spawn(move || {
-                            brightness::Controller::new(b, user_tx, prediction_rx).run();
+                            brightness::Controller::new(
+                                b,
+                                user_tx,
+                                prediction_rx,
+                                event_rx,
+                                idle_dim,
+                                output_followers,
+                                ignore,
+                            )
+                            .run();
                         })
                         .unwrap_or_else(|_| panic!("Unable to start thread: {}", thread_name));
 
                     let predictor = match output_clone.clone() {
                         config::Output::Backlight(backlight_output) => backlight_output.predictor,
                         config::Output::DdcUtil(ddcutil_output) => ddcutil_output.predictor,
+                        config::Output::Gamma(gamma_output) => gamma_output.predictor,
+                        config::Output::Command(command_output) => command_output.predictor,
+                    };
+                    let (capturer_power_tx, capturer_power_rx) = mpsc::channel();
+                    power_txs.push(capturer_power_tx);
+                    let predictor_power = match config.power.separate_data {
+                        true => {
+                            let (predictor_power_tx, predictor_power_rx) = mpsc::channel();
+                            power_txs.push(predictor_power_tx);
+                            Some(power::Listener::new(predictor_power_rx))
+                        }
+                        false => None,
                     };
+                    let adaptive = config.adaptive.clone();
+                    let cooldown = config.cooldown;
+                    let apps = config.apps.clone();
+                    let als_profiles = als_profiles.clone();
                     let thread_name = format!("predictor-{}", output_name);
                     std::thread::Builder::new()
                         .name(thread_name.clone())
@@ -79,20 +214,40 @@ fn main() {
                             let mut frame_capturer: Box<dyn frame::capturer::Capturer> =
                                 match output_capturer {
                                     config::Capturer::Wayland(protocol) => {
-                                        Box::new(frame::capturer::wayland::Capturer::new(protocol))
+                                        Box::new(frame::capturer::wayland::Capturer::new(
+                                            protocol,
+                                            apps,
+                                            focus_tx,
+                                            power::Listener::new(capturer_power_rx),
+                                        ))
                                     }
                                     config::Capturer::None => {
-                                        Box::<frame::capturer::none::Capturer>::default()
+                                        Box::new(frame::capturer::none::Capturer::new(
+                                            power::Listener::new(capturer_power_rx),
+                                        ))
                                     }
                                 };
 
                             let controller = match predictor {
-                                config::Predictor::Manual { thresholds } => {
+                                config::Predictor::Manual { thresholds, mode } => {
                                     Box::new(predictor::controller::manual::Controller::new(
                                         prediction_tx,
                                         user_rx,
                                         als_rx,
                                         thresholds,
+                                        mode,
+                                        max_brightness,
+                                        cooldown,
+                                    ))
+                                        as Box<dyn predictor::Controller>
+                                }
+                                config::Predictor::Levels { levels } => {
+                                    Box::new(predictor::controller::levels::Controller::new(
+                                        prediction_tx,
+                                        user_rx,
+                                        als_rx,
+                                        levels,
+                                        cooldown,
                                     ))
                                         as Box<dyn predictor::Controller>
                                 }
@@ -102,7 +257,12 @@ fn main() {
                                         user_rx,
                                         als_rx,
                                         true,
+                                        predictor_power,
                                         &output_name,
+                                        max_brightness,
+                                        adaptive,
+                                        cooldown,
+                                        als_profiles,
                                     ))
                                         as Box<dyn predictor::Controller>
                                 }
@@ -112,7 +272,7 @@ fn main() {
                         })
                         .unwrap_or_else(|_| panic!("Unable to start thread: {}", thread_name));
 
-                    Some(als_tx)
+                    output_als_txs
                 }
                 Err(err) => {
                     log::warn!(
@@ -121,11 +281,57 @@ fn main() {
                         err
                     );
 
-                    None
+                    vec![]
                 }
             }
         })
         .collect_vec();
+    als_txs.extend(leader_als_txs);
+
+    if !idle_timeouts.is_empty() {
+        std::thread::Builder::new()
+            .name("idle".to_string())
+            .spawn(move || idle::Idle::new(idle_timeouts).run())
+            .expect("Unable to start thread: idle");
+    }
+
+    let (als_power_tx, als_power_rx) = mpsc::channel();
+    power_txs.push(als_power_tx);
+
+    let power = config.power.clone();
+    if power.separate_data || power.max_brightness_percent.is_some() || power.slowdown > 1 {
+        let power_event_txs = event_txs.values().cloned().collect_vec();
+        std::thread::Builder::new()
+            .name("power".to_string())
+            .spawn(move || power::Monitor::new(power, power_event_txs, power_txs).run())
+            .expect("Unable to start thread: power");
+    }
+
+    if config.ignore.session_inactive {
+        let session_txs = event_txs.values().cloned().collect_vec();
+        std::thread::Builder::new()
+            .name("session".to_string())
+            .spawn(move || {
+                if let Err(err) = session::watch(session_txs) {
+                    log::warn!("Unable to watch session state reported by logind: {}", err);
+                }
+            })
+            .expect("Unable to start thread: session");
+    }
+
+    match control::Server::new(event_txs, config.hold.duration) {
+        Ok(server) => {
+            std::thread::Builder::new()
+                .name("control".to_string())
+                .spawn(move || server.run())
+                .expect("Unable to start thread: control");
+        }
+        Err(err) => log::warn!(
+            "Unable to listen for commands on '{}': {}",
+            control::socket_path().display(),
+            err
+        ),
+    }
 
     std::thread::Builder::new()
         .name("als".to_string())
@@ -149,10 +355,107 @@ fn main() {
                 config::Als::None { .. } => Box::<als::none::Als>::default(),
             };
 
-            als::controller::Controller::new(als, als_txs).run();
+            als::controller::Controller::new(als, als_txs, power::Listener::new(als_power_rx))
+                .run();
         })
         .expect("Unable to start thread: als");
 
     log::info!("Continue adjusting brightness and wluma will learn your preference over time.");
     std::thread::park();
 }
+
+fn output_name(output: &config::Output) -> &str {
+    match output {
+        config::Output::Backlight(cfg) => &cfg.name,
+        config::Output::DdcUtil(cfg) => &cfg.name,
+        config::Output::Gamma(cfg) => &cfg.name,
+        config::Output::Command(cfg) => &cfg.name,
+    }
+}
+
+fn output_idle(output: &config::Output) -> Option<&config::Idle> {
+    match output {
+        config::Output::Backlight(cfg) => cfg.idle.as_ref(),
+        config::Output::DdcUtil(cfg) => cfg.idle.as_ref(),
+        config::Output::Gamma(cfg) => cfg.idle.as_ref(),
+        config::Output::Command(cfg) => cfg.idle.as_ref(),
+    }
+}
+
+fn output_follow(output: &config::Output) -> Option<&config::Follow> {
+    match output {
+        config::Output::Backlight(cfg) => cfg.follow.as_ref(),
+        config::Output::DdcUtil(cfg) => cfg.follow.as_ref(),
+        config::Output::Gamma(cfg) => cfg.follow.as_ref(),
+        config::Output::Command(cfg) => cfg.follow.as_ref(),
+    }
+}
+
+/// Configured path of the backlight device, or the one discovered in sysfs.
+fn backlight_path(cfg: &config::BacklightOutput) -> Result<String, Box<dyn Error>> {
+    if let Some(path) = &cfg.path {
+        return Ok(path.clone());
+    }
+
+    let (class_path, find): (_, fn(&Path) -> Option<PathBuf>) = match cfg.keyboard {
+        true => (
+            brightness::LEDS_CLASS_PATH,
+            brightness::find_keyboard_backlight,
+        ),
+        false => (brightness::BACKLIGHT_CLASS_PATH, brightness::find_backlight),
+    };
+
+    let path = find(Path::new(class_path)).ok_or_else(|| {
+        format!(
+            "No device was found in {}, set 'path' in the config",
+            class_path
+        )
+    })?;
+    log::info!("Using '{}' for '{}'", path.display(), cfg.name);
+    Ok(path.to_string_lossy().to_string())
+}
+
+fn create_brightness(
+    output: &config::Output,
+    als_txs: &mut Vec<Sender<String>>,
+) -> Result<Box<dyn brightness::Brightness + Send>, Box<dyn Error>> {
+    match output {
+        config::Output::Backlight(cfg) => match cfg.gamma_min {
+            Some(gamma_min) => backlight_path(cfg)
+                .and_then(|path| brightness::Backlight::new(&path, cfg.min_brightness))
+                .and_then(|b| {
+                    let gamma = brightness::Gamma::new(&cfg.name, gamma_min)?;
+                    Ok(brightness::Stacked::new(b, gamma, gamma_min))
+                })
+                .map(|b| Box::new(b) as Box<dyn brightness::Brightness + Send>),
+            None => backlight_path(cfg)
+                .and_then(|path| brightness::Backlight::new(&path, cfg.min_brightness))
+                .map(|b| Box::new(b) as Box<dyn brightness::Brightness + Send>),
+        },
+        config::Output::DdcUtil(cfg) => {
+            let (vcp_als_tx, vcp_als_rx) = mpsc::channel();
+            als_txs.push(vcp_als_tx);
+
+            brightness::DdcUtil::new(
+                &cfg.name,
+                cfg.min_brightness,
+                cfg.poll_interval,
+                cfg.vcp.clone(),
+                vcp_als_rx,
+            )
+            .map(|b| Box::new(b) as Box<dyn brightness::Brightness + Send>)
+        }
+        config::Output::Gamma(cfg) => brightness::Gamma::new(&cfg.name, cfg.min_brightness)
+            .map(|b| Box::new(b) as Box<dyn brightness::Brightness + Send>),
+        config::Output::Command(cfg) => brightness::Command::new(
+            &cfg.get,
+            &cfg.set,
+            cfg.min_brightness,
+            cfg.max_brightness,
+            cfg.timeout,
+            cfg.poll_interval,
+            cfg.set_interval,
+        )
+        .map(|b| Box::new(b) as Box<dyn brightness::Brightness + Send>),
+    }
+}
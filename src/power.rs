@@ -0,0 +1,306 @@
+use crate::brightness::Event;
+use crate::config::Power;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+pub const POWER_SUPPLY_CLASS_PATH: &str = "/sys/class/power_supply";
+
+const POLL_INTERVAL_SECS: u64 = 5;
+
+/// Power supply as reported by the kernel.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Supply {
+    pub on_battery: bool,
+    pub capacity: Option<u64>,
+}
+
+/// How the power supply currently affects wluma.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct State {
+    /// Whether the device is currently running on battery.
+    pub on_battery: bool,
+    /// Highest allowed brightness (in percent), while saving battery.
+    pub brightness_cap: Option<u64>,
+    /// How many times slower screen contents and ALS should be polled, while saving battery.
+    pub slowdown: u32,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            on_battery: false,
+            brightness_cap: None,
+            slowdown: 1,
+        }
+    }
+}
+
+impl State {
+    fn new(supply: Supply, config: &Power) -> Self {
+        let saving = supply.on_battery
+            && match (config.below_percent, supply.capacity) {
+                (Some(below), Some(capacity)) => capacity < below,
+                _ => true,
+            };
+
+        if saving {
+            Self {
+                on_battery: supply.on_battery,
+                brightness_cap: config.max_brightness_percent,
+                slowdown: config.slowdown.max(1),
+            }
+        } else {
+            Self {
+                on_battery: supply.on_battery,
+                ..Self::default()
+            }
+        }
+    }
+}
+
+/// Keeps the latest power state sent by the monitor, which stays the default without one.
+pub struct Listener {
+    state_rx: Receiver<State>,
+    state: State,
+}
+
+impl Listener {
+    pub fn new(state_rx: Receiver<State>) -> Self {
+        Self {
+            state_rx,
+            state: State::default(),
+        }
+    }
+
+    pub fn state(&mut self) -> State {
+        if let Some(state) = self.state_rx.try_iter().last() {
+            self.state = state;
+        }
+        self.state
+    }
+}
+
+/// Reads the power supply state, any online AC adapter means that we are not running on battery.
+pub fn read(class_path: &Path) -> Supply {
+    let attr = |path: &Path, name: &str| {
+        fs::read_to_string(path.join(name))
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    let (mut has_battery, mut ac_online, mut capacity) = (false, false, None);
+
+    for path in class_path
+        .read_dir()
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+    {
+        match attr(&path, "type").as_str() {
+            "Mains" | "USB" => ac_online |= attr(&path, "online") == "1",
+            // Batteries of peripheral devices (e.g. a wireless mouse) have "Device" scope
+            "Battery" if attr(&path, "scope") != "Device" => {
+                has_battery = true;
+                capacity = attr(&path, "capacity").parse().ok().or(capacity);
+            }
+            _ => {}
+        }
+    }
+
+    Supply {
+        on_battery: has_battery && !ac_online,
+        capacity,
+    }
+}
+
+/// Sends brightness limit to brightness controllers and the whole power state to everyone else.
+pub struct Monitor {
+    class_path: PathBuf,
+    config: Power,
+    state: State,
+    event_txs: Vec<Sender<Event>>,
+    state_txs: Vec<Sender<State>>,
+}
+
+impl Monitor {
+    pub fn new(
+        config: Power,
+        event_txs: Vec<Sender<Event>>,
+        state_txs: Vec<Sender<State>>,
+    ) -> Self {
+        Self {
+            class_path: PathBuf::from(POWER_SUPPLY_CLASS_PATH),
+            config,
+            state: State::default(),
+            event_txs,
+            state_txs,
+        }
+    }
+
+    pub fn run(&mut self) {
+        loop {
+            self.step();
+            thread::sleep(Duration::from_secs(POLL_INTERVAL_SECS));
+        }
+    }
+
+    fn step(&mut self) {
+        let state = State::new(read(&self.class_path), &self.config);
+        if self.state == state {
+            return;
+        }
+
+        log::debug!("Power state changed: {:?}", state);
+        if self.state.brightness_cap != state.brightness_cap {
+            for event_tx in &self.event_txs {
+                event_tx
+                    .send(Event::BrightnessCap(state.brightness_cap))
+                    .expect("Unable to send brightness limit, channel is dead");
+            }
+        }
+        for state_tx in &self.state_txs {
+            state_tx
+                .send(state)
+                .expect("Unable to send power state, channel is dead");
+        }
+        self.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use std::sync::mpsc;
+
+    fn supply(root: &Path, name: &str, attrs: &[(&str, &str)]) {
+        let path = root.join(name);
+        fs::create_dir_all(&path).unwrap();
+        for (attr, value) in attrs {
+            fs::write(path.join(attr), format!("{}\n", value)).unwrap();
+        }
+    }
+
+    fn config() -> Power {
+        Power {
+            separate_data: false,
+            below_percent: None,
+            max_brightness_percent: Some(60),
+            slowdown: 3,
+        }
+    }
+
+    #[test]
+    fn test_read_on_battery() {
+        let power = TempDir::new("power-battery");
+        supply(&power, "AC", &[("type", "Mains"), ("online", "0")]);
+        supply(&power, "BAT0", &[("type", "Battery"), ("capacity", "42")]);
+        supply(
+            &power,
+            "hidpp_battery_0",
+            &[("type", "Battery"), ("scope", "Device"), ("capacity", "5")],
+        );
+
+        assert_eq!(
+            Supply {
+                on_battery: true,
+                capacity: Some(42),
+            },
+            read(&power)
+        );
+    }
+
+    #[test]
+    fn test_read_on_ac() {
+        let power = TempDir::new("power-ac");
+        supply(&power, "AC", &[("type", "Mains"), ("online", "1")]);
+        supply(&power, "BAT0", &[("type", "Battery"), ("capacity", "42")]);
+
+        assert!(!read(&power).on_battery);
+    }
+
+    #[test]
+    fn test_read_without_battery() {
+        let power = TempDir::new("power-desktop");
+        supply(
+            &power,
+            "hidpp_battery_0",
+            &[("type", "Battery"), ("scope", "Device")],
+        );
+
+        assert!(!read(&power).on_battery);
+        assert!(!read(&power.join("missing")).on_battery);
+    }
+
+    #[test]
+    fn test_state_saves_battery_only_below_configured_charge() {
+        let mut config = config();
+        config.below_percent = Some(30);
+
+        let supply = |on_battery, capacity| Supply {
+            on_battery,
+            capacity: Some(capacity),
+        };
+
+        assert_eq!(State::default(), State::new(supply(false, 10), &config));
+        assert_eq!(
+            State {
+                on_battery: true,
+                ..State::default()
+            },
+            State::new(supply(true, 50), &config)
+        );
+        assert_eq!(
+            State {
+                on_battery: true,
+                brightness_cap: Some(60),
+                slowdown: 3,
+            },
+            State::new(supply(true, 20), &config)
+        );
+    }
+
+    #[test]
+    fn test_state_saves_battery_whenever_on_battery() {
+        let supply = Supply {
+            on_battery: true,
+            capacity: Some(90),
+        };
+
+        assert_eq!(Some(60), State::new(supply, &config()).brightness_cap);
+    }
+
+    #[test]
+    fn test_monitor_sends_changes() -> Result<(), Box<dyn std::error::Error>> {
+        let power = TempDir::new("power-monitor");
+        supply(&power, "AC", &[("type", "Mains"), ("online", "1")]);
+        supply(&power, "BAT0", &[("type", "Battery"), ("capacity", "42")]);
+
+        let (event_tx, event_rx) = mpsc::channel();
+        let (state_tx, state_rx) = mpsc::channel();
+        let mut monitor = Monitor::new(config(), vec![event_tx], vec![state_tx]);
+        monitor.class_path = power.to_path_buf();
+        let mut listener = Listener::new(state_rx);
+
+        // nothing is sent while on AC
+        monitor.step();
+        assert!(event_rx.try_recv().is_err());
+        assert_eq!(State::default(), listener.state());
+
+        supply(&power, "AC", &[("online", "0")]);
+        monitor.step();
+        monitor.step();
+        assert_eq!(Event::BrightnessCap(Some(60)), event_rx.try_recv()?);
+        assert!(event_rx.try_recv().is_err());
+        assert_eq!(3, listener.state().slowdown);
+
+        // listener keeps the latest state
+        assert!(listener.state().on_battery);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,730 @@
+use super::data::{unix_now, Data, Entry, DATA_VERSION};
+use crate::{als, config};
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::ops::RangeInclusive;
+
+pub const USAGE: &str = "\
+Usage:
+  wluma data list [--output <name>]                    show learned entries
+  wluma data export [--output <name>] [--format json|csv]
+                                                       print learned data
+  wluma data import <file> [--output <name>]           add entries from an exported file
+  wluma data merge <from> <into>                       add entries of one output to another
+  wluma data forget [--output <name>] [--profile <name>] [--luma <min>-<max>] [--app <id>]
+                                                       remove matching entries
+  wluma data undo [--output <name>] [<count>]          undo the last learned entries
+  wluma data revert [--output <name>] [<date>] <time>  undo everything learned since then";
+
+const CSV_HEADER: &str = "output,profile,luma,brightness,time,learned_at,uses,app";
+
+/// Learned entry of an output, as a row of exported CSV.
+#[derive(Debug, Serialize, Deserialize)]
+struct Row {
+    output: String,
+    profile: String,
+    luma: u8,
+    brightness: f64,
+    time: Option<String>,
+    learned_at: Option<u64>,
+    uses: u64,
+    app: Option<String>,
+}
+
+impl Row {
+    fn new(output: &str, entry: &Entry) -> Self {
+        Self {
+            output: output.to_string(),
+            profile: entry.lux.clone(),
+            luma: entry.luma,
+            brightness: entry.brightness,
+            time: entry.time.map(time_of_day),
+            learned_at: entry.learned_at,
+            uses: entry.uses,
+            app: entry.app.clone(),
+        }
+    }
+
+    fn entry(&self) -> Result<Entry, String> {
+        Ok(Entry {
+            time: self.time.as_deref().map(parse_time_of_day).transpose()?,
+            learned_at: self.learned_at,
+            uses: self.uses,
+            app: self.app.clone(),
+            ..Entry::new(&self.profile, self.luma, self.brightness)
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Format {
+    Json,
+    Csv,
+}
+
+/// Filter of entries to forget, it matches everything that isn't restricted.
+#[derive(Debug, PartialEq, Eq, Default)]
+struct Filter {
+    output: Option<String>,
+    profile: Option<String>,
+    luma: Option<RangeInclusive<u8>>,
+    app: Option<String>,
+}
+
+impl Filter {
+    fn matches(&self, entry: &Entry) -> bool {
+        self.profile.as_ref().map_or(true, |p| *p == entry.lux)
+            && self.luma.as_ref().map_or(true, |l| l.contains(&entry.luma))
+            && self
+                .app
+                .as_ref()
+                .map_or(true, |a| entry.app.as_ref() == Some(a))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    List {
+        output: Option<String>,
+    },
+    Export {
+        output: Option<String>,
+        format: Format,
+    },
+    Import {
+        path: String,
+        output: Option<String>,
+    },
+    Merge {
+        from: String,
+        into: String,
+    },
+    Forget(Filter),
+    Undo {
+        output: Option<String>,
+        count: usize,
+    },
+    Revert {
+        output: Option<String>,
+        at: u64,
+    },
+}
+
+impl Command {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let (name, args) = args.split_first().ok_or("Missing data command")?;
+
+        let (mut output, mut format, mut profile, mut luma, mut app) =
+            (None, None, None, None, None);
+        let mut positional = vec![];
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("Missing value of {}", arg))
+            };
+            match arg.as_str() {
+                "--output" => output = Some(value()?),
+                "--format" => format = Some(parse_format(&value()?)?),
+                "--profile" => profile = Some(value()?),
+                "--luma" => luma = Some(parse_luma(&value()?)?),
+                "--app" => app = Some(value()?),
+                _ => positional.push(arg.clone()),
+            }
+        }
+
+        let only = |allowed: bool| match allowed {
+            true => Ok(()),
+            false => Err(format!("Unsupported option of 'data {}'", name)),
+        };
+
+        match (name.as_str(), positional.as_slice()) {
+            ("list", []) => {
+                only(format.is_none() && profile.is_none() && luma.is_none() && app.is_none())?;
+                Ok(Self::List { output })
+            }
+            ("export", []) => {
+                only(profile.is_none() && luma.is_none() && app.is_none())?;
+                Ok(Self::Export {
+                    output,
+                    format: format.unwrap_or(Format::Json),
+                })
+            }
+            ("import", [path]) => {
+                only(format.is_none() && profile.is_none() && luma.is_none() && app.is_none())?;
+                Ok(Self::Import {
+                    path: path.clone(),
+                    output,
+                })
+            }
+            ("merge", [from, into]) => {
+                only(
+                    output.is_none()
+                        && format.is_none()
+                        && profile.is_none()
+                        && luma.is_none()
+                        && app.is_none(),
+                )?;
+                Ok(Self::Merge {
+                    from: from.clone(),
+                    into: into.clone(),
+                })
+            }
+            ("forget", []) => {
+                only(format.is_none())?;
+                if output.is_none() && profile.is_none() && luma.is_none() && app.is_none() {
+                    Err(
+                        "Refusing to forget everything, specify --output, --profile, --luma or --app",
+                    )?;
+                }
+                Ok(Self::Forget(Filter {
+                    output,
+                    profile,
+                    luma,
+                    app,
+                }))
+            }
+            ("undo", [] | [_]) => {
+                only(format.is_none() && profile.is_none() && luma.is_none() && app.is_none())?;
+                let count = match positional.first() {
+                    Some(count) => count
+                        .parse()
+                        .map_err(|_| format!("Invalid number of entries: {}", count))?,
+                    None => 1,
+                };
+                Ok(Self::Undo { output, count })
+            }
+            ("revert", [_] | [_, _]) => {
+                only(format.is_none() && profile.is_none() && luma.is_none() && app.is_none())?;
+                Ok(Self::Revert {
+                    output,
+                    at: parse_timestamp(&positional.join(" "))?,
+                })
+            }
+            _ => Err(format!(
+                "Invalid data command: {}",
+                std::iter::once(name).chain(&positional).join(" ")
+            )),
+        }
+    }
+}
+
+fn parse_format(format: &str) -> Result<Format, String> {
+    match format {
+        "json" => Ok(Format::Json),
+        "csv" => Ok(Format::Csv),
+        _ => Err(format!("Unsupported format: {}", format)),
+    }
+}
+
+/// Either a time today, e.g. `14:30`, or a date and time, e.g. `2024-05-01 14:30`.
+fn parse_timestamp(time: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid time: {}", time);
+    let local = match NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M") {
+        Ok(local) => local,
+        Err(_) => Local::now()
+            .date_naive()
+            .and_time(NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| invalid())?),
+    };
+    local
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|local| local.timestamp().max(0) as u64)
+        .ok_or_else(invalid)
+}
+
+/// Either a single luma value or an inclusive range, e.g. `20-40`.
+fn parse_luma(luma: &str) -> Result<RangeInclusive<u8>, String> {
+    let invalid = || format!("Invalid luma range: {}", luma);
+    let (min, max) = luma.split_once('-').unwrap_or((luma, luma));
+    let min = min.trim().parse().map_err(|_| invalid())?;
+    let max = max.trim().parse().map_err(|_| invalid())?;
+    match min <= max && max <= 100 {
+        true => Ok(min..=max),
+        false => Err(invalid()),
+    }
+}
+
+/// Runs a `wluma data` command and returns what should be printed. Data is changed under a lock,
+/// so the commands are safe to use while wluma is running, it picks up the changes within seconds.
+pub fn run(args: &[String]) -> Result<String, Box<dyn Error>> {
+    if args.first().is_some_and(|arg| arg == "help") {
+        return Ok(USAGE.to_string());
+    }
+
+    let command = Command::parse(args).map_err(|err| format!("{}\n\n{}", err, USAGE))?;
+
+    match command {
+        Command::List { output } => Ok(list(&read_all(&output)?)),
+        Command::Export { output, format } => {
+            let data = read_all(&output)?;
+            match format {
+                Format::Json => to_json(&data),
+                Format::Csv => to_csv(&data),
+            }
+        }
+        Command::Import { path, output } => {
+            let imported = parse_import(&fs::read_to_string(&path)?)?;
+            let profiles = monotonic_profiles()?;
+
+            let mut summary = vec![];
+            let by_output = imported.into_iter().into_group_map_by(|data| {
+                output.clone().unwrap_or_else(|| data.output_name.clone())
+            });
+            for (name, data) in by_output.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+                let identity = data.iter().find_map(|data| data.identity.clone());
+                let entries = data.into_iter().flat_map(|data| data.entries).collect_vec();
+                summary.push(format!("Imported {} entries into {}", entries.len(), name));
+                Data::update(&name, |data| {
+                    data.identity = data.identity.take().or(identity);
+                    learn_all(data, entries, profiles.as_deref());
+                })?;
+            }
+            Ok(summary.join("\n"))
+        }
+        Command::Merge { from, into } => {
+            if !Data::output_names()?.contains(&from) {
+                Err(format!("No learned data of {}", from))?;
+            }
+            let entries = Data::read(&from)?.entries;
+            let count = entries.len();
+            let profiles = monotonic_profiles()?;
+            Data::update(&into, |data| learn_all(data, entries, profiles.as_deref()))?;
+            Ok(format!(
+                "Merged {} entries of {} into {}",
+                count, from, into
+            ))
+        }
+        Command::Forget(filter) => {
+            let mut forgotten = 0;
+            for name in output_names(&filter.output)? {
+                Data::update(&name, |data| {
+                    forgotten += data.forget(unix_now(), |entry| filter.matches(entry));
+                })?;
+            }
+            Ok(format!("Forgot {} entries", forgotten))
+        }
+        Command::Undo { output, count } => {
+            let name = journaled_output_name(&output)?;
+            let mut undone = 0;
+            Data::update(&name, |data| undone = data.undo(count))?;
+            Ok(format!("Undid {} changes of {}", undone, name))
+        }
+        Command::Revert { output, at } => {
+            let name = journaled_output_name(&output)?;
+            let (mut undone, mut oldest) = (0, None);
+            Data::update(&name, |data| {
+                oldest = data.journal.first().map(|learned| learned.at);
+                undone = data.revert_to(at);
+            })?;
+            Ok(reverted(&name, at, undone, oldest))
+        }
+    }
+}
+
+fn output_names(output: &Option<String>) -> Result<Vec<String>, Box<dyn Error>> {
+    let names = Data::output_names()?;
+    match output {
+        Some(name) if names.contains(name) => Ok(vec![name.clone()]),
+        Some(name) => Err(format!("No learned data of {}", name))?,
+        None => Ok(names),
+    }
+}
+
+/// Only the most recently learned entries can be undone, so the state at the given time
+/// is not restored when the oldest of them was learned after it.
+fn reverted(name: &str, at: u64, undone: usize, oldest: Option<u64>) -> String {
+    let summary = format!(
+        "Undid {} entries of {} learned since {}",
+        undone,
+        name,
+        date_time(at)
+    );
+    match oldest {
+        Some(oldest) if oldest > at => format!(
+            "{}\nThe revert is partial, entries learned before {} are no longer remembered and were kept",
+            summary,
+            date_time(oldest)
+        ),
+        _ => summary,
+    }
+}
+
+/// The output to undo learning of, which only has to be given when several outputs learned something.
+fn journaled_output_name(output: &Option<String>) -> Result<String, Box<dyn Error>> {
+    if let Some(name) = output {
+        return Ok(output_names(&Some(name.clone()))?.remove(0));
+    }
+
+    let names = read_all(&None)?
+        .into_iter()
+        .filter(|data| !data.journal.is_empty())
+        .map(|data| data.output_name)
+        .collect_vec();
+    match names.as_slice() {
+        [] => Err("Nothing was learned recently")?,
+        [name] => Ok(name.clone()),
+        _ => Err(format!(
+            "Several outputs learned something recently, specify --output with one of: {}",
+            names.join(", ")
+        ))?,
+    }
+}
+
+fn read_all(output: &Option<String>) -> Result<Vec<Data>, Box<dyn Error>> {
+    output_names(output)?
+        .iter()
+        .map(|name| Data::read(name))
+        .collect()
+}
+
+/// Imported entries take precedence over the learned ones they contradict,
+/// and the more recent ones over the older ones. They can be undone like a single learned entry.
+fn learn_all(data: &mut Data, entries: Vec<Entry>, profiles: Option<&[String]>) {
+    let entries = entries
+        .into_iter()
+        .sorted_by_key(|entry| entry.learned_at)
+        .collect();
+    data.learn(unix_now(), entries, profiles);
+}
+
+/// Profiles ordered from the darkest to the brightest, when imported entries should replace
+/// the ones of other profiles they contradict, as the daemon does while learning.
+fn monotonic_profiles() -> Result<Option<Vec<String>>, Box<dyn Error>> {
+    let config = config::load().map_err(|err| format!("Unable to load config: {}", err))?;
+    Ok(config
+        .adaptive
+        .monotonic
+        .then(|| als::configured_profiles(&config.als)))
+}
+
+fn list(data: &[Data]) -> String {
+    data.iter()
+        .map(|data| {
+            let width = data
+                .entries
+                .iter()
+                .map(|entry| entry.lux.len())
+                .chain(["profile".len()])
+                .max()
+                .unwrap_or_default();
+
+            let header = format!(
+                "  {:<width$}  luma  brightness  time   learned     uses  app",
+                "profile"
+            );
+            let rows = data.entries.iter().map(|entry| {
+                format!(
+                    "  {:<width$}  {:>4}  {:>9.1}%  {:<5}  {:<10}  {:>4}  {}",
+                    entry.lux,
+                    entry.luma,
+                    entry.brightness * 100.,
+                    entry.time.map_or("-".to_string(), time_of_day),
+                    entry.learned_at.map_or("-".to_string(), date),
+                    entry.uses,
+                    entry.app.as_deref().unwrap_or("-")
+                )
+            });
+
+            let journal = data.journal.iter().rev().map(|learned| {
+                let change = match learned.entries.as_slice() {
+                    [] => format!("forgot {}", learned.replaced.len()),
+                    [entry] => format!(
+                        "{}, luma {}{}: {:.1}%",
+                        entry.lux,
+                        entry.luma,
+                        entry
+                            .app
+                            .as_ref()
+                            .map_or(String::new(), |app| format!(" in {}", app)),
+                        entry.brightness * 100.
+                    ),
+                    entries => format!("imported {}", entries.len()),
+                };
+                format!(
+                    "  {}  {}{}",
+                    date_time(learned.at),
+                    change,
+                    match learned.replaced.len() {
+                        0 => String::new(),
+                        _ if learned.entries.is_empty() => String::new(),
+                        replaced => format!(" (replaced {})", replaced),
+                    }
+                )
+            });
+            let journal_header =
+                (!data.journal.is_empty()).then(|| "\n  recently learned".to_string());
+
+            std::iter::once(format!(
+                "{} ({} entries)",
+                data.output_name,
+                data.entries.len()
+            ))
+            .chain(std::iter::once(header))
+            .chain(rows)
+            .chain(journal_header)
+            .chain(journal)
+            .join("\n")
+        })
+        .join("\n\n")
+}
+
+fn time_of_day(minutes: u16) -> String {
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+fn parse_time_of_day(time: &str) -> Result<u16, String> {
+    let invalid = || format!("Invalid time of day: {}", time);
+    let (hours, minutes) = time.split_once(':').ok_or_else(invalid)?;
+    let hours = hours.parse::<u16>().map_err(|_| invalid())?;
+    let minutes = minutes.parse::<u16>().map_err(|_| invalid())?;
+    match hours < 24 && minutes < 60 {
+        true => Ok(hours * 60 + minutes),
+        false => Err(invalid()),
+    }
+}
+
+fn date(unix_secs: u64) -> String {
+    DateTime::from_timestamp(unix_secs as i64, 0).map_or("-".to_string(), |date| {
+        date.with_timezone(&Local).format("%Y-%m-%d").to_string()
+    })
+}
+
+fn date_time(unix_secs: u64) -> String {
+    DateTime::from_timestamp(unix_secs as i64, 0).map_or("-".to_string(), |date| {
+        date.with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    })
+}
+
+/// Learned data without the journal and unconfirmed adjustments, which only matter to the daemon.
+fn to_json(data: &[Data]) -> Result<String, Box<dyn Error>> {
+    let exported = data
+        .iter()
+        .map(|data| Data {
+            max_brightness: None,
+            journal: vec![],
+            unconfirmed: vec![],
+            ..data.clone()
+        })
+        .collect_vec();
+    Ok(serde_json::to_string_pretty(&exported)?)
+}
+
+fn to_csv(data: &[Data]) -> Result<String, Box<dyn Error>> {
+    // Header is written even without any entries, so that the file is recognized on import
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    writer.write_record(CSV_HEADER.split(','))?;
+    for data in data {
+        for entry in &data.entries {
+            writer.serialize(Row::new(&data.output_name, entry))?;
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+fn parse_csv(content: &str) -> Result<Vec<Data>, Box<dyn Error>> {
+    let mut data: Vec<Data> = vec![];
+    for row in csv::Reader::from_reader(content.as_bytes()).deserialize() {
+        let row: Row = row?;
+        let entry = row
+            .entry()
+            .map_err(|err| format!("Entry of {}: {}", row.output, err))?;
+
+        match data.iter_mut().find(|data| data.output_name == row.output) {
+            Some(data) => data.entries.push(entry),
+            None => data.push(Data {
+                entries: vec![entry],
+                ..Data::new(&row.output)
+            }),
+        }
+    }
+    Ok(data)
+}
+
+/// Reads what was exported as CSV or JSON, or a data file that wluma stores.
+fn parse_import(content: &str) -> Result<Vec<Data>, Box<dyn Error>> {
+    let data = match content.lines().next() {
+        Some(CSV_HEADER) => parse_csv(content)?,
+        _ => match serde_json::from_str::<Vec<Data>>(content) {
+            Ok(data) => data,
+            Err(_) => vec![serde_yaml::from_str::<Data>(content)?],
+        },
+    };
+
+    if let Some(data) = data.iter().find(|data| data.version != DATA_VERSION) {
+        Err(format!(
+            "Data of {} has unsupported version {}",
+            data.output_name, data.version
+        ))?;
+    }
+    for data in &data {
+        if data
+            .entries
+            .iter()
+            .any(|e| !(0. ..=1.).contains(&e.brightness))
+        {
+            Err(format!(
+                "Data of {}: Brightness must be between 0 and 1",
+                data.output_name
+            ))?;
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split(' ').map(String::from).collect()
+    }
+
+    fn sample() -> Vec<Data> {
+        vec![
+            Data {
+                entries: vec![
+                    Entry {
+                        learned_at: Some(1700000000),
+                        uses: 3,
+                        ..Entry::new("dim", 20, 0.25).with_time(Some(21 * 60 + 5))
+                    },
+                    Entry::new("bright", 80, 1.).with_app(Some("org.gimp.GIMP".to_string())),
+                ],
+                ..Data::new("Dell Inc. DELL P2415Q, \"left\"")
+            },
+            Data {
+                entries: vec![Entry::new("dark", 0, 0.75)],
+                ..Data::new("eDP-1")
+            },
+        ]
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            Ok(Command::Export {
+                output: Some("eDP-1".to_string()),
+                format: Format::Csv
+            }),
+            Command::parse(&args("export --format csv --output eDP-1"))
+        );
+        assert_eq!(
+            Ok(Command::Forget(Filter {
+                profile: Some("dim".to_string()),
+                luma: Some(20..=40),
+                ..Filter::default()
+            })),
+            Command::parse(&args("forget --profile dim --luma 20-40"))
+        );
+        assert!(Command::parse(&args("forget")).is_err());
+        assert!(Command::parse(&args("forget --luma 40-20")).is_err());
+        assert!(Command::parse(&args("list --profile dim")).is_err());
+        assert!(Command::parse(&args("merge eDP-1")).is_err());
+        assert!(Command::parse(&args("export --format xml")).is_err());
+    }
+
+    #[test]
+    fn test_parse_undo_and_revert() {
+        assert_eq!(
+            Ok(Command::Undo {
+                output: None,
+                count: 1
+            }),
+            Command::parse(&args("undo"))
+        );
+        assert_eq!(
+            Ok(Command::Undo {
+                output: Some("eDP-1".to_string()),
+                count: 3
+            }),
+            Command::parse(&args("undo --output eDP-1 3"))
+        );
+
+        let at = chrono::TimeZone::with_ymd_and_hms(&Local, 2024, 5, 1, 14, 30, 0)
+            .earliest()
+            .unwrap()
+            .timestamp() as u64;
+        assert_eq!(
+            Ok(Command::Revert { output: None, at }),
+            Command::parse(&args("revert 2024-05-01 14:30"))
+        );
+        assert!(Command::parse(&args("revert yesterday")).is_err());
+        assert!(Command::parse(&args("undo some")).is_err());
+    }
+
+    #[test]
+    fn test_revert_reports_when_it_is_partial() {
+        let hour = 60 * 60;
+        assert_eq!(
+            format!("Undid 2 entries of eDP-1 learned since {}", date_time(hour)),
+            reverted("eDP-1", hour, 2, Some(hour - 10))
+        );
+        assert_eq!(
+            format!(
+                "Undid 20 entries of eDP-1 learned since {}\nThe revert is partial, entries learned before {} are no longer remembered and were kept",
+                date_time(hour),
+                date_time(2 * hour)
+            ),
+            reverted("eDP-1", hour, 20, Some(2 * hour))
+        );
+    }
+
+    #[test]
+    fn test_json_roundtrip() -> Result<(), Box<dyn Error>> {
+        assert_eq!(sample(), parse_import(&to_json(&sample())?)?);
+
+        let identified = vec![Data {
+            identity: Some("DEL DELL P2415Q 7FH7K65I0Z2L".to_string()),
+            ..Data::new("DEL DELL P2415Q 7FH7K65I0Z2L")
+        }];
+        assert_eq!(identified, parse_import(&to_json(&identified)?)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_rejects_brightness_out_of_range() -> Result<(), Box<dyn Error>> {
+        let data = vec![Data {
+            entries: vec![Entry::new("dim", 20, 96000.)],
+            ..Data::new("eDP-1")
+        }];
+        assert!(parse_import(&to_json(&data)?).is_err());
+        assert!(parse_import(&to_csv(&data)?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_roundtrip() -> Result<(), Box<dyn Error>> {
+        assert_eq!(sample(), parse_import(&to_csv(&sample())?)?);
+        assert_eq!(Vec::<Data>::new(), parse_import(&to_csv(&[])?)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_forget_filter() {
+        let filter = Filter {
+            profile: Some("dim".to_string()),
+            luma: Some(10..=20),
+            ..Filter::default()
+        };
+
+        assert!(filter.matches(&Entry::new("dim", 20, 0.5)));
+        assert!(!filter.matches(&Entry::new("dim", 21, 0.5)));
+        assert!(!filter.matches(&Entry::new("bright", 15, 0.5)));
+
+        let filter = Filter {
+            app: Some("mpv".to_string()),
+            ..Filter::default()
+        };
+        assert!(filter.matches(&Entry::new("dim", 20, 0.5).with_app(Some("mpv".to_string()))));
+        assert!(!filter.matches(&Entry::new("dim", 20, 0.5)));
+    }
+}
@@ -1,37 +1,74 @@
-use super::{
-    Controller as _, INITIAL_TIMEOUT_SECS, NEXT_ALS_COOLDOWN_RESET, PENDING_COOLDOWN_RESET,
-};
-use crate::predictor::data::{Data, Entry};
+use super::{Als, Controller as _, Cooldown, INITIAL_TIMEOUT_SECS};
+use crate::config::{self, Adaptive};
+use crate::power;
+use crate::predictor::data::{self, unix_now, Data, Entry};
+use chrono::{Local, Timelike};
+use itertools::Itertools;
 use std::sync::mpsc::{Receiver, Sender};
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often expired entries are removed and usage statistics are saved.
+const COMPACTION_INTERVAL_SECS: u64 = 60 * 60;
+/// How often learned data is checked for changes made by `wluma data` commands.
+const RELOAD_INTERVAL_SECS: u64 = 5;
 
 pub struct Controller {
     prediction_tx: Sender<u64>,
     user_rx: Receiver<u64>,
-    als_rx: Receiver<String>,
-    pending_cooldown: u8,
+    pending_cooldown: Cooldown,
     pending: Option<Entry>,
+    /// Adjustment that waits to be confirmed, its brightness is kept while conditions stay similar.
+    awaiting_confirmation: Option<Entry>,
     data: Data,
+    data_modified: Option<SystemTime>,
+    last_reload: Option<Instant>,
     stateful: bool,
     initial_brightness: Option<u64>,
-    last_als: Option<String>,
-    next_als: Option<String>,
-    next_als_cooldown: u8,
+    als: Als,
     output_name: String,
+    /// Name that learned data is stored under, the identity of the monitor once it is known.
+    storage_name: String,
+    identity: Option<String>,
+    /// Power state, only while preferences on battery are learned separately.
+    power: Option<power::Listener>,
+    on_battery: bool,
+    max_brightness: u64,
+    config: Adaptive,
+    last_prediction: Option<u64>,
+    last_compaction: Option<Instant>,
+    unsaved_uses: bool,
+    profiles: Vec<String>,
+    anchor: Option<f64>,
+    focused_app: Option<String>,
 }
 
 impl super::Controller for Controller {
     fn adjust(&mut self, luma: u8) {
-        if self.last_als.is_none() {
-            // ALS controller is expected to send the initial value on this channel asap
-            self.last_als = self
-                .als_rx
-                .recv_timeout(Duration::from_secs(INITIAL_TIMEOUT_SECS))
-                .map_or_else(
-                    |e| panic!("Did not receive initial ALS value in time: {e:?}"),
-                    Some,
-                );
+        if let Some(power) = &mut self.power {
+            let on_battery = power.state().on_battery;
+            self.switch_power_data(on_battery);
+        }
 
+        let compaction_interval = Duration::from_secs(COMPACTION_INTERVAL_SECS);
+        if self
+            .last_compaction
+            .map_or(true, |last| last.elapsed() >= compaction_interval)
+        {
+            self.compact(unix_now());
+        }
+
+        let reload_interval = Duration::from_secs(RELOAD_INTERVAL_SECS);
+        if self
+            .last_reload
+            .map_or(true, |last| last.elapsed() >= reload_interval)
+        {
+            self.reload_if_modified();
+        }
+
+        let initial = !self.als.is_known();
+        let lux = &self.als.profile();
+
+        if initial {
             // Brightness controller is expected to send the initial value on this channel asap
             let initial_brightness = self
                 .user_rx
@@ -41,6 +78,9 @@ impl super::Controller for Controller {
                     Some,
                 );
 
+            // Uncertain predictions only move brightness part of the way from the current one
+            self.anchor = initial_brightness.map(|b| data::fraction(b, self.max_brightness));
+
             // If there are no learned entries yet, we will use this as the first data point,
             // assuming that user is happy with the current brightness settings
             if self.data.entries.is_empty() {
@@ -48,122 +88,509 @@ impl super::Controller for Controller {
             };
         }
 
-        match self.als_rx.try_iter().last() {
-            new_als @ Some(_) if self.next_als != new_als => {
-                self.next_als = new_als;
-                self.next_als_cooldown = NEXT_ALS_COOLDOWN_RESET;
-            }
-            _ if self.next_als_cooldown > 1 => {
-                self.next_als_cooldown -= 1;
-            }
-            _ if self.next_als_cooldown == 1 => {
-                self.next_als_cooldown = 0;
-                self.last_als = self.next_als.take();
+        self.process(lux, luma);
+    }
+
+    /// From now on, learned data follows the monitor between docks regardless of its config name,
+    /// and data learned under the config name is where each monitor starts from.
+    /// Monitors that can't be identified use the data of the config name.
+    fn identify(&mut self, identity: Option<&str>) {
+        if self.identity.as_deref() == identity {
+            return;
+        }
+
+        // Anything that user has just adjusted belongs to the previous monitor
+        if self.pending.is_some() {
+            self.learn();
+        }
+
+        let Some(identity) = identity else {
+            self.identity = None;
+            self.storage_name = self.output_name.clone();
+            self.load(&self.data_name(self.on_battery));
+            return;
+        };
+
+        let name = Data::identity_name(identity);
+        if self.stateful {
+            for suffix in ["", "-battery"] {
+                let (from, into) = (
+                    format!("{}{}", self.output_name, suffix),
+                    format!("{}{}", name, suffix),
+                );
+                match Data::adopt(&from, &into, identity, self.max_brightness) {
+                    Ok(true) => log::info!(
+                        "[{}] Copied learned data of '{}' to '{}', which identifies the monitor",
+                        self.output_name,
+                        from,
+                        into
+                    ),
+                    Ok(false) => {}
+                    Err(err) => log::warn!(
+                        "[{}] Unable to copy learned data of '{}' to '{}': {}",
+                        self.output_name,
+                        from,
+                        into,
+                        err
+                    ),
+                }
             }
-            _ => {}
         }
 
-        let lux = &self.last_als.clone().expect("ALS value must be known");
-        self.process(lux, luma);
+        self.identity = Some(identity.to_string());
+        self.storage_name = name;
+        self.load(&self.data_name(self.on_battery));
+    }
+
+    /// Preferences are learned for the focused app, and predicted from them once there are any.
+    /// Global preferences, which apps fall back to until then, are learned while no app is focused,
+    /// and from adjustments of the fallback predictions in apps without preferences of their own.
+    fn focus(&mut self, app: Option<&str>) {
+        log::debug!("[{}] Focused app: {:?}", self.output_name, app);
+        self.focused_app = app.map(str::to_string);
+    }
+
+    /// Older entries matter less, their weight halves with every half-life.
+    fn weight(&self, entry: &Entry) -> f64 {
+        match (self.config.half_life, entry.learned_at) {
+            (Some(half_life), Some(learned_at)) => {
+                let age = unix_now().saturating_sub(learned_at) as f64;
+                0.5_f64.powf(age / half_life.as_secs_f64().max(1.))
+            }
+            _ => 1.,
+        }
     }
 }
 
 impl Controller {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         prediction_tx: Sender<u64>,
         user_rx: Receiver<u64>,
         als_rx: Receiver<String>,
         stateful: bool,
+        power: Option<power::Listener>,
         output_name: &str,
+        max_brightness: u64,
+        config: Adaptive,
+        cooldown: config::Cooldown,
+        profiles: Vec<String>,
     ) -> Self {
-        let data = if stateful {
-            Data::load(output_name)
-        } else {
-            Data::new(output_name)
-        };
-
-        Self {
+        let mut controller = Self {
             prediction_tx,
             user_rx,
-            als_rx,
-            pending_cooldown: 0,
+            pending_cooldown: Cooldown::new(cooldown.adjustment),
             pending: None,
-            data,
+            awaiting_confirmation: None,
+            data: Data::new(output_name),
+            data_modified: None,
+            last_reload: None,
             stateful,
             initial_brightness: None,
-            last_als: None,
-            next_als: None,
-            next_als_cooldown: 0,
+            als: Als::new(als_rx, cooldown.als),
             output_name: output_name.to_string(),
+            storage_name: output_name.to_string(),
+            identity: None,
+            power,
+            on_battery: false,
+            max_brightness,
+            config,
+            last_prediction: None,
+            last_compaction: None,
+            unsaved_uses: false,
+            profiles,
+            anchor: None,
+            focused_app: None,
+        };
+        controller.load(output_name);
+        controller
+    }
+
+    fn data_name(&self, on_battery: bool) -> String {
+        match on_battery {
+            true => format!("{}-battery", self.storage_name),
+            false => self.storage_name.clone(),
         }
     }
 
+    fn load(&mut self, data_name: &str) {
+        if self.stateful {
+            self.data_modified = Data::modified(data_name);
+            self.data = Data::load(data_name, self.max_brightness);
+        } else {
+            self.data = Data::new(data_name);
+        }
+
+        if self.data.identity.is_none() && self.storage_name != self.output_name {
+            self.data.identity = self.identity.clone();
+        }
+    }
+
+    /// Picks up changes to the learned data made by `wluma data` commands while wluma is running.
+    fn reload_if_modified(&mut self) {
+        self.last_reload = Some(Instant::now());
+        if !self.stateful || self.data.read_only {
+            return;
+        }
+
+        let modified = Data::modified(&self.data.output_name);
+        if modified.is_some() && modified != self.data_modified {
+            log::info!(
+                "[{}] Reloading learned data, as it was changed outside of wluma",
+                self.output_name
+            );
+            let data_name = self.data.output_name.clone();
+            self.load(&data_name);
+        }
+    }
+
+    /// Changes learned data and saves it if needed, while no `wluma data` command can change it in the meantime.
+    fn update(&mut self, update: impl FnOnce(&mut Self) -> bool) {
+        let _lock = match self.stateful {
+            true => Data::lock(&self.data.output_name)
+                .map_err(|err| {
+                    log::warn!(
+                        "[{}] Unable to lock learned data: {}",
+                        self.output_name,
+                        err
+                    )
+                })
+                .ok(),
+            false => None,
+        };
+
+        self.reload_if_modified();
+        if update(self) {
+            self.save();
+        }
+    }
+
+    /// Preferences on battery are learned separately from the ones on AC.
+    fn switch_power_data(&mut self, on_battery: bool) {
+        if self.on_battery == on_battery {
+            return;
+        }
+
+        // Anything that user has just adjusted belongs to the previous power state
+        if self.pending.is_some() {
+            self.learn();
+        }
+
+        log::debug!(
+            "[{}] Switching to data learned on {}",
+            self.output_name,
+            if on_battery { "battery" } else { "AC" }
+        );
+
+        self.load(&self.data_name(on_battery));
+        self.on_battery = on_battery;
+        self.last_compaction = None;
+    }
+
     fn process(&mut self, lux: &str, luma: u8) {
         let initial_brightness = self.initial_brightness.take();
         let user_changed_brightness = self.user_rx.try_iter().last().or(initial_brightness);
 
         if let Some(brightness) = user_changed_brightness {
+            let brightness = data::fraction(brightness, self.max_brightness);
+            self.anchor = Some(brightness);
             self.pending = match &self.pending {
-                // First time we notice user adjusting brightness, freeze lux and luma...
-                None => Some(Entry::new(lux, luma, brightness)),
+                // First time we notice user adjusting brightness, freeze lux, luma and focused app...
+                None => Some(
+                    Entry::new(lux, luma, brightness)
+                        .with_time(self.time_of_day())
+                        .with_app(self.focused_app.clone()),
+                ),
                 // ... but as user keeps changing brightness,
                 // allow some time for them to reach the desired brightness level for the pending lux and luma
-                Some(Entry { lux, luma, .. }) => Some(Entry::new(lux, *luma, brightness)),
+                Some(pending) => Some(Entry {
+                    brightness,
+                    ..pending.clone()
+                }),
             };
             // Every time user changed brightness, reset the cooldown period
-            self.pending_cooldown = PENDING_COOLDOWN_RESET;
-        } else if self.pending_cooldown > 0 {
-            self.pending_cooldown -= 1;
-        } else if self.pending.is_some() {
-            self.learn();
-        } else {
-            self.predict(lux, luma);
+            self.pending_cooldown.reset();
+            self.awaiting_confirmation = None;
+        } else if !self.pending_cooldown.active() {
+            if self.pending.is_some() {
+                self.learn();
+            } else if !self.keeps_unconfirmed(lux, luma) {
+                self.predict(lux, luma);
+            }
         }
     }
 
+    /// Brightness that user set is not predicted over before the adjustment is confirmed,
+    /// until the lux, luma or focused app it was made with change.
+    fn keeps_unconfirmed(&mut self, lux: &str, luma: u8) -> bool {
+        let keeps = self.awaiting_confirmation.as_ref().is_some_and(|entry| {
+            entry.lux == lux
+                && entry.luma.abs_diff(luma) <= data::SIMILAR_LUMA
+                && entry.app == self.focused_app
+        });
+        if !keeps {
+            self.awaiting_confirmation = None;
+        }
+        keeps
+    }
+
     fn learn(&mut self) {
-        let pending = self.pending.take().expect("No pending entry to learn");
-        log::debug!("[{}] Learning {:?}", self.output_name, pending);
+        let mut pending = self.pending.take().expect("No pending entry to learn");
+        if self.decays() {
+            pending.learned_at = Some(unix_now());
+        }
+        self.update(|controller| {
+            if controller.config.confirm && !controller.data.confirm(&pending) {
+                log::debug!(
+                    "[{}] Waiting for a similar adjustment to confirm {:?}",
+                    controller.output_name,
+                    pending
+                );
+                controller.awaiting_confirmation = Some(pending);
+                return true;
+            }
+            log::debug!("[{}] Learning {:?}", controller.output_name, pending);
+
+            // Brightness was predicted from the global preferences, so they were off as well
+            let mut entries = vec![];
+            if pending.app.is_some() && !controller.has_entries(&pending.lux, &pending.app) {
+                entries.push(Entry {
+                    app: None,
+                    ..pending.clone()
+                });
+            }
+            entries.push(pending);
+
+            let profiles = controller
+                .config
+                .monotonic
+                .then_some(&controller.profiles[..]);
+            controller.data.learn(unix_now(), entries, profiles);
+            true
+        });
+    }
 
-        self.data.entries.retain(|entry| {
-            let different_env = entry.lux != pending.lux;
+    fn save(&mut self) {
+        if self.stateful {
+            if let Err(err) = self.data.save() {
+                log::error!(
+                    "[{}] Unable to save learned data: {}",
+                    self.output_name,
+                    err
+                );
+            }
+            self.data_modified = Data::modified(&self.data.output_name);
+        }
+        self.unsaved_uses = false;
+    }
 
-            let same_env_darker_screen = entry.lux == pending.lux
-                && entry.luma < pending.luma
-                && entry.brightness >= pending.brightness;
+    fn profile_index(&self, lux: &str) -> Option<usize> {
+        self.profiles.iter().position(|profile| profile == lux)
+    }
 
-            let same_env_brighter_screen = entry.lux == pending.lux
-                && entry.luma > pending.luma
-                && entry.brightness <= pending.brightness;
+    /// Keeps the prediction between the ones of darker and brighter profiles.
+    fn monotonic(
+        &self,
+        prediction: f64,
+        lux: &str,
+        luma: u8,
+        time: Option<u16>,
+        app: Option<&str>,
+    ) -> f64 {
+        let Some(index) = self.profile_index(lux) else {
+            return prediction;
+        };
 
-            different_env || same_env_darker_screen || same_env_brighter_screen
-        });
+        let (mut lower, mut upper) = (0_f64, 1_f64);
+        for (i, profile) in self.profiles.iter().enumerate() {
+            if let Some(other) = self.interpolate(&self.data.entries, profile, luma, time, app) {
+                if i < index {
+                    lower = lower.max(other);
+                } else if i > index {
+                    upper = upper.min(other);
+                }
+            }
+        }
 
-        self.data.entries.push(pending);
+        let monotonic = prediction.max(lower).min(upper);
+        if monotonic != prediction {
+            log::debug!(
+                "[{}] Adjusting prediction {:.3} to {:.3} to keep it consistent with other ALS profiles",
+                self.output_name,
+                prediction,
+                monotonic
+            );
+        }
+        monotonic
+    }
 
-        self.data
-            .entries
-            .sort_unstable_by(|x, y| x.lux.cmp(&y.lux).then(x.luma.cmp(&y.luma)));
+    /// Whether learned entries lose their weight and expire over time.
+    fn decays(&self) -> bool {
+        self.config.half_life.is_some() || self.config.max_age.is_some()
+    }
 
-        if self.stateful {
-            self.data.save().expect("Unable to save data");
+    /// Removes expired entries and saves usage statistics.
+    fn compact(&mut self, now: u64) {
+        self.last_compaction = Some(Instant::now());
+        self.update(|controller| controller.expire(now));
+    }
+
+    /// Whether anything changed that needs to be saved.
+    fn expire(&mut self, now: u64) -> bool {
+        let len = self.data.entries.len();
+        let mut stamped = false;
+        if self.decays() {
+            // Entries learned before decay was enabled start aging from now on
+            for entry in &mut self.data.entries {
+                stamped |= entry.learned_at.is_none();
+                entry.learned_at.get_or_insert(now);
+            }
+        }
+
+        if let Some(max_age) = self.config.max_age {
+            self.data.entries.retain(|entry| {
+                entry.learned_at.map_or(true, |learned_at| {
+                    now.saturating_sub(learned_at) <= max_age.as_secs()
+                })
+            });
         }
+
+        let expired = len - self.data.entries.len();
+        if expired > 0 {
+            log::debug!("[{}] Removed {} expired entries", self.output_name, expired);
+        }
+
+        expired > 0 || stamped || self.unsaved_uses
+    }
+
+    /// Current time of day in minutes since midnight, if it is used for predictions.
+    fn time_of_day(&self) -> Option<u16> {
+        self.config.time_of_day.then(|| {
+            let now = Local::now();
+            (now.hour() * 60 + now.minute()) as u16
+        })
+    }
+
+    /// Predicts brightness from the data of the given profile, preferably learned for the focused app,
+    /// or if there is none yet, from the closest darker and brighter profiles,
+    /// with less confidence the further they are.
+    fn predict_with_confidence(
+        &self,
+        lux: &str,
+        luma: u8,
+        time: Option<u16>,
+    ) -> Option<(f64, f64)> {
+        for app in [self.focused_app.as_deref(), None].into_iter().dedup() {
+            if let Some(prediction) = self.interpolate(&self.data.entries, lux, luma, time, app) {
+                return Some(match self.config.monotonic {
+                    true => (self.monotonic(prediction, lux, luma, time, app), 1.),
+                    false => (prediction, 1.),
+                });
+            }
+        }
+
+        let index = self.profile_index(lux)? as i32;
+        let known = [self.focused_app.as_deref(), None]
+            .into_iter()
+            .dedup()
+            .map(|app| {
+                self.profiles
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, profile)| {
+                        let prediction =
+                            self.interpolate(&self.data.entries, profile, luma, time, app)?;
+                        Some((i as i32, prediction))
+                    })
+                    .collect_vec()
+            })
+            .find(|known| !known.is_empty())
+            .unwrap_or_default();
+
+        let darker = known.iter().filter(|(i, _)| *i < index).collect_vec();
+        let brighter = known.iter().filter(|(i, _)| *i > index).collect_vec();
+
+        let line = |(i1, p1): (i32, f64), (i2, p2): (i32, f64)| {
+            p1 + (p2 - p1) * (index - i1) as f64 / (i2 - i1) as f64
+        };
+
+        let (prediction, steps) = match (darker.as_slice(), brighter.as_slice()) {
+            // Interpolate between the closest profiles on both sides...
+            ([.., &darker], [&brighter, ..]) => (
+                line(darker, brighter),
+                (index - darker.0).min(brighter.0 - index),
+            ),
+            // ... or extrapolate the trend of the two closest ones on one side
+            ([.., &further, &closer], []) | ([], [&closer, &further, ..]) => {
+                (line(further, closer), (index - closer.0).abs())
+            }
+            ([&closer], []) | ([], [&closer, ..]) => (closer.1, (index - closer.0).abs()),
+            _ => return None,
+        };
+
+        let confidence = self.config.cross_profile_confidence.powi(steps);
+        Some((prediction.clamp(0., 1.), confidence))
     }
 
     fn predict(&mut self, lux: &str, luma: u8) {
-        if let Some(prediction) = self.interpolate(&self.data.entries, lux, luma) {
+        let time = self.time_of_day();
+        if let Some((prediction, confidence)) = self.predict_with_confidence(lux, luma, time) {
+            // Uncertain predictions only move brightness part of the way from the last certain one
+            let prediction = match self.anchor {
+                Some(anchor) if confidence < 1. => anchor + (prediction - anchor) * confidence,
+                _ => prediction,
+            };
+            if confidence == 1. {
+                self.anchor = Some(prediction);
+            }
+
+            let prediction = data::raw(prediction, self.max_brightness);
             log::trace!("Prediction: {} (lux: {}, luma: {})", prediction, lux, luma);
+
+            if self.last_prediction != Some(prediction) {
+                self.last_prediction = Some(prediction);
+                self.count_use(lux, luma, time);
+            }
+
             self.prediction_tx
                 .send(prediction)
                 .expect("Unable to send predicted brightness value, channel is dead");
         }
     }
+
+    fn has_entries(&self, lux: &str, app: &Option<String>) -> bool {
+        self.data
+            .entries
+            .iter()
+            .any(|entry| entry.lux == lux && entry.app == *app)
+    }
+
+    fn count_use(&mut self, lux: &str, luma: u8, time: Option<u16>) {
+        // Entries of the focused app are the ones predictions come from, once it has some
+        let app = match self.has_entries(lux, &self.focused_app) {
+            true => self.focused_app.clone(),
+            false => None,
+        };
+        let closest = self
+            .data
+            .entries
+            .iter_mut()
+            .filter(|entry| entry.lux == lux && entry.app == app)
+            .min_by(|a, b| {
+                super::distance(a, luma, time).total_cmp(&super::distance(b, luma, time))
+            });
+
+        if let Some(entry) = closest {
+            entry.uses += 1;
+            self.unsaved_uses = true;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use itertools::{iproduct, Itertools};
+    use itertools::iproduct;
     use std::collections::HashSet;
     use std::error::Error;
     use std::sync::mpsc;
@@ -178,10 +605,42 @@ mod tests {
         let (prediction_tx, prediction_rx) = mpsc::channel();
         als_tx.send(ALS_BRIGHT.to_string())?;
         user_tx.send(0)?;
-        let controller = Controller::new(prediction_tx, user_rx, als_rx, false, "Dell 1");
+        let controller = Controller::new(
+            prediction_tx,
+            user_rx,
+            als_rx,
+            false,
+            None,
+            "Dell 1",
+            100,
+            Adaptive::default(),
+            config::Cooldown {
+                adjustment: Duration::from_secs(60),
+                als: Duration::from_secs(60),
+            },
+            vec![],
+        );
         Ok((controller, user_tx, prediction_rx))
     }
 
+    #[test]
+    fn test_switch_power_data() -> Result<(), Box<dyn Error>> {
+        let (mut controller, user_tx, _) = setup()?;
+        controller.data.entries = vec![Entry::new(ALS_DIM, 10, 0.7)];
+
+        // User adjusts brightness right before unplugging AC...
+        user_tx.send(33)?;
+        controller.process(ALS_DIM, 66);
+        controller.switch_power_data(true);
+
+        // ... which is learned before switching to the separate battery data
+        assert_eq!(None, controller.pending);
+        assert_eq!("Dell 1-battery", controller.data.output_name);
+        assert_eq!(Vec::<Entry>::new(), controller.data.entries);
+
+        Ok(())
+    }
+
     #[test]
     fn test_process_first_user_change() -> Result<(), Box<dyn Error>> {
         let (mut controller, user_tx, _) = setup()?;
@@ -190,8 +649,8 @@ mod tests {
         user_tx.send(33)?;
         controller.process(ALS_DIM, 66);
 
-        assert_eq!(Some(Entry::new(ALS_DIM, 66, 33)), controller.pending);
-        assert_eq!(PENDING_COOLDOWN_RESET, controller.pending_cooldown);
+        assert_eq!(Some(Entry::new(ALS_DIM, 66, 0.33)), controller.pending);
+        assert!(controller.pending_cooldown.active());
 
         Ok(())
     }
@@ -211,8 +670,8 @@ mod tests {
         user_tx.send(36)?;
         controller.process(ALS_DARK, 16);
 
-        assert_eq!(Some(Entry::new(ALS_DIM, 66, 36)), controller.pending);
-        assert_eq!(PENDING_COOLDOWN_RESET, controller.pending_cooldown);
+        assert_eq!(Some(Entry::new(ALS_DIM, 66, 0.36)), controller.pending);
+        assert!(controller.pending_cooldown.active());
 
         Ok(())
     }
@@ -229,19 +688,20 @@ mod tests {
         user_tx.send(35)?;
         controller.process(ALS_DARK, 16);
 
-        for i in 1..=PENDING_COOLDOWN_RESET {
+        for i in 1..=15 {
             // User doesn't change brightness anymore, so even if lux or luma change, we are in cooldown period
             controller.process(ALS_BRIGHT, i);
-            assert_eq!(PENDING_COOLDOWN_RESET - i, controller.pending_cooldown);
-            assert_eq!(Some(Entry::new(ALS_DIM, 66, 35)), controller.pending);
+            assert!(controller.pending_cooldown.active());
+            assert_eq!(Some(Entry::new(ALS_DIM, 66, 0.35)), controller.pending);
         }
 
-        // One final process will trigger the learning
+        // Once the cooldown is over, the next process will trigger the learning
+        controller.pending_cooldown.until = Some(Instant::now());
         controller.process(ALS_DARK, 17);
 
         assert_eq!(None, controller.pending);
-        assert_eq!(0, controller.pending_cooldown);
-        assert_eq!(vec![Entry::new(ALS_DIM, 66, 35)], controller.data.entries);
+        assert!(!controller.pending_cooldown.active());
+        assert_eq!(vec![Entry::new(ALS_DIM, 66, 0.35)], controller.data.entries);
 
         Ok(())
     }
@@ -260,21 +720,27 @@ mod tests {
     fn test_learn_data_cleanup() -> Result<(), Box<dyn Error>> {
         let (mut controller, _, _) = setup()?;
 
-        let pending = Entry::new(ALS_DIM, 20, 30);
+        let pending = Entry::new(ALS_DIM, 20, 0.3);
 
         let all_als = vec![ALS_DARK, ALS_DIM, ALS_BRIGHT];
         let all_combinations: HashSet<_> = iproduct!(-1i32..=1, -1i32..=1, -1i32..=1)
-            .map(|(i, j, k)| Entry::new(all_als[(1 + i) as usize], (20 + j) as u8, (30 + k) as u64))
+            .map(|(i, j, k)| {
+                Entry::new(
+                    all_als[(1 + i) as usize],
+                    (20 + j) as u8,
+                    (30 + k) as f64 / 100.,
+                )
+            })
             .collect();
 
         let to_be_deleted: HashSet<_> = vec![
             // same env darker screen
-            Entry::new(ALS_DIM, 19, 29),
+            Entry::new(ALS_DIM, 19, 0.29),
             // same env same screen
-            Entry::new(ALS_DIM, 20, 29),
-            Entry::new(ALS_DIM, 20, 31),
+            Entry::new(ALS_DIM, 20, 0.29),
+            Entry::new(ALS_DIM, 20, 0.31),
             // same env brighter screen
-            Entry::new(ALS_DIM, 21, 31),
+            Entry::new(ALS_DIM, 21, 0.31),
         ]
         .into_iter()
         .collect();
@@ -324,10 +790,8 @@ mod tests {
     #[test]
     fn test_predict_no_data_points_for_current_als_profile() -> Result<(), Box<dyn Error>> {
         let (mut controller, _, prediction_rx) = setup()?;
-        controller.data.entries = vec![
-            Entry::new(ALS_DARK, 50, 100),
-            Entry::new(ALS_BRIGHT, 60, 100),
-        ];
+        controller.data.entries =
+            vec![Entry::new(ALS_DARK, 50, 1.), Entry::new(ALS_BRIGHT, 60, 1.)];
 
         // predict() should not be called with no data, but just in case confirm we don't panic
         controller.predict(ALS_DIM, 20);
@@ -340,7 +804,7 @@ mod tests {
     #[test]
     fn test_predict_one_data_point() -> Result<(), Box<dyn Error>> {
         let (mut controller, _, prediction_rx) = setup()?;
-        controller.data.entries = vec![Entry::new(ALS_DIM, 10, 15)];
+        controller.data.entries = vec![Entry::new(ALS_DIM, 10, 0.15)];
 
         controller.predict(ALS_DIM, 20);
 
@@ -351,7 +815,7 @@ mod tests {
     #[test]
     fn test_predict_known_conditions() -> Result<(), Box<dyn Error>> {
         let (mut controller, _, prediction_rx) = setup()?;
-        controller.data.entries = vec![Entry::new(ALS_DIM, 10, 15), Entry::new(ALS_DIM, 20, 30)];
+        controller.data.entries = vec![Entry::new(ALS_DIM, 10, 0.15), Entry::new(ALS_DIM, 20, 0.3)];
 
         controller.predict(ALS_DIM, 20);
 
@@ -363,9 +827,9 @@ mod tests {
     fn test_predict_approximate() -> Result<(), Box<dyn Error>> {
         let (mut controller, _, prediction_rx) = setup()?;
         controller.data.entries = vec![
-            Entry::new(ALS_DIM, 10, 15),
-            Entry::new(ALS_DIM, 20, 30),
-            Entry::new(ALS_DIM, 100, 100),
+            Entry::new(ALS_DIM, 10, 0.15),
+            Entry::new(ALS_DIM, 20, 0.3),
+            Entry::new(ALS_DIM, 100, 1.),
         ];
 
         // Approximated using weighted distance to all known points:
@@ -382,11 +846,11 @@ mod tests {
     fn test_predict_only_uses_data_for_current_als_profile() -> Result<(), Box<dyn Error>> {
         let (mut controller, _, prediction_rx) = setup()?;
         controller.data.entries = vec![
-            Entry::new(ALS_DIM, 10, 15),
-            Entry::new(ALS_DIM, 20, 30),
-            Entry::new(ALS_DIM, 100, 100),
-            Entry::new(ALS_DARK, 50, 100),
-            Entry::new(ALS_BRIGHT, 51, 100),
+            Entry::new(ALS_DIM, 10, 0.15),
+            Entry::new(ALS_DIM, 20, 0.3),
+            Entry::new(ALS_DIM, 100, 1.),
+            Entry::new(ALS_DARK, 50, 1.),
+            Entry::new(ALS_BRIGHT, 51, 1.),
         ];
 
         controller.predict(ALS_DIM, 50);
@@ -394,4 +858,473 @@ mod tests {
         assert_eq!(43, prediction_rx.try_recv()?);
         Ok(())
     }
+
+    #[test]
+    fn test_learn_keeps_entries_from_distant_time_of_day() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, _) = setup()?;
+
+        let afternoon = Entry::new(ALS_DIM, 20, 0.6).with_time(Some(15 * 60));
+        let evening = Entry::new(ALS_DIM, 20, 0.5).with_time(Some(17 * 60 + 30));
+        controller.data.entries = vec![afternoon.clone(), evening];
+
+        // Dimmer screen late at night doesn't contradict the afternoon, but replaces the evening preference
+        let night = Entry::new(ALS_DIM, 20, 0.3).with_time(Some(19 * 60));
+        controller.pending = Some(night.clone());
+        controller.learn();
+
+        assert_eq!(vec![afternoon, night], controller.data.entries);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolate_prefers_similar_time_of_day() -> Result<(), Box<dyn Error>> {
+        let (controller, _, _) = setup()?;
+        let entries = vec![
+            Entry::new(ALS_DIM, 50, 0.2).with_time(Some(23 * 60)),
+            Entry::new(ALS_DIM, 50, 0.8).with_time(Some(15 * 60)),
+        ];
+
+        let night = controller
+            .interpolate(&entries, ALS_DIM, 50, Some(30), None)
+            .unwrap();
+        let afternoon = controller
+            .interpolate(&entries, ALS_DIM, 50, Some(14 * 60), None)
+            .unwrap();
+
+        assert!(night < 0.4, "{}", night);
+        assert!(afternoon > 0.6, "{}", afternoon);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolate_data_without_time_of_day() -> Result<(), Box<dyn Error>> {
+        let (controller, _, _) = setup()?;
+        let entries = vec![Entry::new(ALS_DIM, 10, 0.15), Entry::new(ALS_DIM, 20, 0.3)];
+
+        assert_eq!(
+            Some(0.3),
+            controller.interpolate(&entries, ALS_DIM, 20, None, None)
+        );
+        assert!(controller
+            .interpolate(&entries, ALS_DIM, 20, Some(12 * 60), None)
+            .is_some_and(|prediction| (0.15..0.3).contains(&prediction)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_removes_expired_entries() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, _) = setup()?;
+        controller.config.max_age = Some(Duration::from_secs(100));
+
+        let expired = Entry {
+            learned_at: Some(800),
+            ..Entry::new(ALS_DIM, 10, 0.2)
+        };
+        let recent = Entry {
+            learned_at: Some(950),
+            ..Entry::new(ALS_DIM, 20, 0.3)
+        };
+        let unknown = Entry::new(ALS_DIM, 30, 0.4);
+        controller.data.entries = vec![expired, recent.clone(), unknown.clone()];
+
+        controller.compact(1000);
+
+        // Entries learned before decay was enabled start aging now
+        let unknown = Entry {
+            learned_at: Some(1000),
+            ..unknown
+        };
+        assert_eq!(vec![recent, unknown], controller.data.entries);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_entry_that_started_aging() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, _) = setup()?;
+        let replaced = Entry::new(ALS_DIM, 20, 0.3);
+        controller.data.entries = vec![replaced.clone()];
+
+        controller.pending = Some(Entry::new(ALS_DIM, 20, 0.6));
+        controller.learn();
+
+        // Decay gets enabled after the entry was learned
+        controller.config.half_life = Some(Duration::from_secs(30 * 24 * 60 * 60));
+        controller.compact(1000);
+        assert_eq!(Some(1000), controller.data.entries[0].learned_at);
+
+        controller.data.undo(1);
+        assert_eq!(vec![replaced], controller.data.entries);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolate_prefers_recent_entries() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, _) = setup()?;
+        controller.config.half_life = Some(Duration::from_secs(30 * 24 * 60 * 60));
+
+        let entries = vec![
+            Entry {
+                learned_at: Some(unix_now() - 365 * 24 * 60 * 60),
+                ..Entry::new(ALS_DIM, 40, 0.8)
+            },
+            Entry {
+                learned_at: Some(unix_now()),
+                ..Entry::new(ALS_DIM, 60, 0.2)
+            },
+        ];
+
+        let prediction = controller
+            .interpolate(&entries, ALS_DIM, 50, None, None)
+            .unwrap();
+        assert!(prediction < 0.21, "{}", prediction);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_predict_counts_uses_of_closest_entry() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, prediction_rx) = setup()?;
+        controller.data.entries = vec![Entry::new(ALS_DIM, 10, 0.15), Entry::new(ALS_DIM, 20, 0.3)];
+
+        controller.predict(ALS_DIM, 18);
+        controller.predict(ALS_DIM, 18);
+        controller.predict(ALS_DIM, 12);
+
+        // Repeated predictions of the same value are only counted once
+        assert_eq!(
+            vec![1, 1],
+            controller.data.entries.iter().map(|e| e.uses).collect_vec()
+        );
+        assert_eq!(3, prediction_rx.try_iter().count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_predict_counts_uses_of_entries_of_focused_app() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, _prediction_rx) = setup()?;
+        let mpv = Entry::new(ALS_DIM, 20, 0.9).with_app(Some("mpv".to_string()));
+        controller.data.entries = vec![Entry::new(ALS_DIM, 20, 0.3), mpv];
+
+        controller.focus(Some("foot"));
+        controller.predict(ALS_DIM, 20);
+        controller.focus(Some("mpv"));
+        controller.predict(ALS_DIM, 20);
+
+        assert_eq!(
+            vec![1, 1],
+            controller.data.entries.iter().map(|e| e.uses).collect_vec()
+        );
+
+        Ok(())
+    }
+
+    fn profiles(controller: &mut Controller) {
+        controller.config.cross_profile_confidence = 0.5;
+        controller.profiles = vec![ALS_DARK, ALS_DIM, ALS_BRIGHT]
+            .into_iter()
+            .map(String::from)
+            .collect();
+    }
+
+    #[test]
+    fn test_predict_between_neighbouring_profiles() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, _) = setup()?;
+        profiles(&mut controller);
+        controller.data.entries = vec![
+            Entry::new(ALS_DARK, 50, 0.2),
+            Entry::new(ALS_BRIGHT, 50, 0.6),
+        ];
+
+        let (prediction, confidence) = controller
+            .predict_with_confidence(ALS_DIM, 50, None)
+            .unwrap();
+
+        assert!((prediction - 0.4).abs() < 1e-9, "{}", prediction);
+        assert_eq!(0.5, confidence);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_predict_beyond_known_profiles() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, _) = setup()?;
+        profiles(&mut controller);
+        controller.data.entries = vec![Entry::new(ALS_DARK, 50, 0.2), Entry::new(ALS_DIM, 50, 0.5)];
+        assert_eq!(
+            Some((0.8, 0.5)),
+            controller.predict_with_confidence(ALS_BRIGHT, 50, None)
+        );
+
+        controller.data.entries = vec![Entry::new(ALS_BRIGHT, 50, 0.7)];
+        assert_eq!(
+            Some((0.7, 0.25)),
+            controller.predict_with_confidence(ALS_DARK, 50, None)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_predict_limits_uncertain_predictions() -> Result<(), Box<dyn Error>> {
+        let (mut controller, user_tx, prediction_rx) = setup()?;
+        profiles(&mut controller);
+        controller.data.entries = vec![Entry::new(ALS_BRIGHT, 50, 0.7)];
+
+        // User sets brightness to 30, confidence of the prediction from the closest profile is 50%
+        user_tx.send(30)?;
+        controller.process(ALS_DIM, 50);
+        controller.pending = None;
+        controller.predict(ALS_DIM, 50);
+
+        assert_eq!(50, prediction_rx.try_recv()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_predict_limits_uncertain_predictions_after_restart() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, prediction_rx) = setup()?;
+        profiles(&mut controller);
+        controller.data.entries = vec![Entry::new(ALS_DARK, 50, 0.8)];
+
+        // Brightness was 0 at startup, confidence of the prediction two profiles away is 25%
+        controller.adjust(50);
+        assert_eq!(20, prediction_rx.try_recv()?);
+
+        // Uncertain prediction doesn't become the anchor of the following ones
+        assert_eq!(Some(0.), controller.anchor);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_learn_prunes_contradicting_profiles() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, _) = setup()?;
+        profiles(&mut controller);
+        controller.config.monotonic = true;
+
+        let darker_consistent = Entry::new(ALS_DARK, 50, 0.3);
+        let brighter_consistent = Entry::new(ALS_BRIGHT, 40, 0.6);
+        controller.data.entries = vec![
+            darker_consistent.clone(),
+            // Darker room and brighter screen contents, yet a brighter screen
+            Entry::new(ALS_DARK, 60, 0.5),
+            brighter_consistent.clone(),
+            // Brighter room and the same screen contents, yet a dimmer screen
+            Entry::new(ALS_BRIGHT, 50, 0.3),
+        ];
+
+        let pending = Entry::new(ALS_DIM, 50, 0.4);
+        controller.pending = Some(pending.clone());
+        controller.learn();
+
+        assert_eq!(
+            vec![brighter_consistent, darker_consistent, pending],
+            controller.data.entries
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_predict_is_monotonic_in_ambient_light() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, _) = setup()?;
+        profiles(&mut controller);
+        controller.data.entries = vec![
+            Entry::new(ALS_DARK, 50, 0.5),
+            Entry::new(ALS_DIM, 50, 0.4),
+            Entry::new(ALS_BRIGHT, 50, 0.8),
+        ];
+
+        assert_eq!(
+            Some((0.4, 1.)),
+            controller.predict_with_confidence(ALS_DIM, 50, None)
+        );
+
+        controller.config.monotonic = true;
+        assert_eq!(
+            Some((0.5, 1.)),
+            controller.predict_with_confidence(ALS_DIM, 50, None)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_identify_stores_data_by_monitor() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, _) = setup()?;
+
+        controller.identify(Some("DEL DELL P2415Q 7FH7K65I0Z2L"));
+        assert_eq!("DEL DELL P2415Q 7FH7K65I0Z2L", controller.data.output_name);
+        assert_eq!(
+            Some("DEL DELL P2415Q 7FH7K65I0Z2L".to_string()),
+            controller.data.identity
+        );
+
+        controller.switch_power_data(true);
+        assert_eq!(
+            "DEL DELL P2415Q 7FH7K65I0Z2L-battery",
+            controller.data.output_name
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_identify_switches_data_when_monitor_is_swapped() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, _) = setup()?;
+
+        controller.identify(Some("DEL DELL P2415Q 1"));
+        controller.data.entries = vec![Entry::new(ALS_DIM, 50, 0.3)];
+
+        // Adjustment made just before the swap belongs to the previous monitor
+        controller.pending = Some(Entry::new(ALS_DIM, 50, 0.6));
+        controller.identify(Some("DEL DELL U2720Q 2"));
+        assert_eq!("DEL DELL U2720Q 2", controller.data.output_name);
+        assert_eq!(None, controller.pending);
+
+        controller.identify(None);
+        assert_eq!("Dell 1", controller.data.output_name);
+        assert_eq!(None, controller.data.identity);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_learning_restores_pruned_entries() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, _) = setup()?;
+        profiles(&mut controller);
+        controller.config.monotonic = true;
+
+        let learned = vec![
+            Entry::new(ALS_BRIGHT, 50, 0.3),
+            Entry::new(ALS_DARK, 60, 0.5),
+            Entry::new(ALS_DIM, 50, 0.6),
+        ];
+        controller.data.entries = learned.clone();
+
+        // Accidental bump of brightness contradicts everything learned before
+        controller.pending = Some(Entry::new(ALS_DIM, 50, 0.4));
+        controller.learn();
+        assert_eq!(1, controller.data.entries.len());
+
+        controller.data.undo(1);
+        assert_eq!(learned, controller.data.entries);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_learn_only_confirmed_adjustments() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, _) = setup()?;
+        controller.config.confirm = true;
+
+        controller.pending = Some(Entry::new(ALS_DIM, 50, 0.9));
+        controller.learn();
+        controller.pending = Some(Entry::new(ALS_DIM, 50, 0.4));
+        controller.learn();
+        assert_eq!(Vec::<Entry>::new(), controller.data.entries);
+
+        // A similar adjustment in the same conditions confirms the preference
+        controller.pending = Some(Entry::new(ALS_DIM, 55, 0.45));
+        controller.learn();
+        assert_eq!(vec![Entry::new(ALS_DIM, 55, 0.45)], controller.data.entries);
+        assert_eq!(
+            vec![Entry::new(ALS_DIM, 50, 0.9)],
+            controller.data.unconfirmed
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unconfirmed_adjustment_is_kept_until_conditions_change() -> Result<(), Box<dyn Error>> {
+        let (mut controller, user_tx, prediction_rx) = setup()?;
+        controller.config.confirm = true;
+        controller.data.entries = vec![Entry::new(ALS_DIM, 50, 0.2)];
+
+        user_tx.send(90)?;
+        controller.process(ALS_DIM, 50);
+        controller.pending_cooldown.until = Some(Instant::now());
+        controller.process(ALS_DIM, 50);
+        assert_eq!(None, controller.pending);
+        assert_eq!(
+            vec![Entry::new(ALS_DIM, 50, 0.9)],
+            controller.data.unconfirmed
+        );
+
+        // Brightness that user set is not overridden while waiting for confirmation
+        controller.process(ALS_DIM, 50);
+        controller.process(ALS_DIM, 55);
+        assert!(prediction_rx.try_recv().is_err());
+
+        // Until the conditions change
+        controller.process(ALS_DIM, 80);
+        assert!(prediction_rx.try_recv().is_ok());
+        assert_eq!(None, controller.awaiting_confirmation);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_learn_and_predict_per_app() -> Result<(), Box<dyn Error>> {
+        let (mut controller, user_tx, _) = setup()?;
+        let global = Entry::new(ALS_DIM, 50, 0.5);
+        controller.data.entries = vec![global.clone()];
+
+        // Adjustment is learned for the app that was focused when it started...
+        controller.focus(Some("mpv"));
+        user_tx.send(90)?;
+        controller.process(ALS_DIM, 50);
+        controller.focus(Some("foot"));
+        controller.pending_cooldown.until = Some(Instant::now());
+        controller.process(ALS_DIM, 50);
+
+        // ... and for the global preferences it was predicted from, as it had none of its own yet
+        let mpv = Entry::new(ALS_DIM, 50, 0.9).with_app(Some("mpv".to_string()));
+        let global = Entry {
+            app: None,
+            ..mpv.clone()
+        };
+        assert_eq!(vec![global.clone(), mpv], controller.data.entries);
+
+        // Once it has some, its adjustments don't replace the preferences of other apps
+        controller.focus(Some("mpv"));
+        user_tx.send(70)?;
+        controller.process(ALS_DIM, 50);
+        controller.pending_cooldown.until = Some(Instant::now());
+        controller.process(ALS_DIM, 50);
+        assert_eq!(global, controller.data.entries[0]);
+        assert_eq!(
+            Some((0.7, 1.)),
+            controller.predict_with_confidence(ALS_DIM, 50, None)
+        );
+
+        // Apps without preferences of their own fall back to the global ones
+        controller.focus(Some("foot"));
+        assert_eq!(
+            Some((0.9, 1.)),
+            controller.predict_with_confidence(ALS_DIM, 50, None)
+        );
+
+        // Which are learned while no app is focused
+        controller.focus(None);
+        user_tx.send(30)?;
+        controller.process(ALS_DIM, 50);
+        controller.pending_cooldown.until = Some(Instant::now());
+        controller.process(ALS_DIM, 50);
+        controller.focus(Some("foot"));
+        assert_eq!(
+            Some((0.3, 1.)),
+            controller.predict_with_confidence(ALS_DIM, 50, None)
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,133 @@
+use super::Als;
+use crate::config;
+use std::{
+    collections::HashMap,
+    sync::mpsc::{Receiver, Sender},
+};
+
+/// Sets a fixed brightness level for each ALS profile, ignoring screen contents.
+/// Brightness changes made by user are respected until the ALS profile changes.
+pub struct Controller {
+    prediction_tx: Sender<u64>,
+    user_rx: Receiver<u64>,
+    levels: HashMap<String, u64>,
+    predicted_als: Option<String>,
+    als: Als,
+}
+
+impl super::Controller for Controller {
+    fn adjust(&mut self, _luma: u8) {
+        let lux = &self.als.profile();
+        self.process(lux);
+    }
+}
+
+impl Controller {
+    pub fn new(
+        prediction_tx: Sender<u64>,
+        user_rx: Receiver<u64>,
+        als_rx: Receiver<String>,
+        levels: HashMap<String, u64>,
+        cooldown: config::Cooldown,
+    ) -> Self {
+        Self {
+            prediction_tx,
+            user_rx,
+            levels,
+            predicted_als: None,
+            als: Als::new(als_rx, cooldown.als),
+        }
+    }
+
+    fn process(&mut self, lux: &str) {
+        // User changes are not learned, they are only kept until the next ALS profile change
+        self.user_rx.try_iter().for_each(drop);
+
+        if self.predicted_als.as_deref() != Some(lux) {
+            self.predicted_als = Some(lux.to_string());
+            self.predict(lux);
+        }
+    }
+
+    fn predict(&mut self, lux: &str) {
+        if let Some(&prediction) = self.levels.get(lux) {
+            log::trace!("Prediction: {} (lux: {})", prediction, lux);
+            self.prediction_tx
+                .send(prediction)
+                .expect("Unable to send predicted brightness value, channel is dead");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    const ALS_UNKNOWN: &str = "not-configured-threshold";
+    const ALS_DIM: &str = "dim";
+    const ALS_BRIGHT: &str = "bright";
+
+    fn setup() -> Result<(Controller, Sender<u64>, Receiver<u64>), Box<dyn Error>> {
+        let (als_tx, als_rx) = mpsc::channel();
+        let (user_tx, user_rx) = mpsc::channel();
+        let (prediction_tx, prediction_rx) = mpsc::channel();
+        als_tx.send(ALS_DIM.to_string())?;
+        user_tx.send(0)?;
+
+        let levels = [(ALS_DIM.to_string(), 1), (ALS_BRIGHT.to_string(), 0)]
+            .into_iter()
+            .collect();
+
+        let cooldown = config::Cooldown {
+            adjustment: Duration::from_secs(60),
+            als: Duration::from_secs(60),
+        };
+        let controller = Controller::new(prediction_tx, user_rx, als_rx, levels, cooldown);
+        Ok((controller, user_tx, prediction_rx))
+    }
+
+    #[test]
+    fn test_predicts_level_for_als_profile() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, prediction_rx) = setup()?;
+
+        controller.process(ALS_DIM);
+        assert_eq!(1, prediction_rx.try_recv()?);
+
+        controller.process(ALS_BRIGHT);
+        assert_eq!(0, prediction_rx.try_recv()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_predicts_only_when_als_profile_changes() -> Result<(), Box<dyn Error>> {
+        let (mut controller, user_tx, prediction_rx) = setup()?;
+
+        controller.process(ALS_DIM);
+        assert_eq!(1, prediction_rx.try_recv()?);
+
+        // User changes are respected while ALS profile stays the same
+        user_tx.send(2)?;
+        controller.process(ALS_DIM);
+        controller.process(ALS_DIM);
+        assert!(prediction_rx.try_recv().is_err());
+
+        controller.process(ALS_BRIGHT);
+        assert_eq!(0, prediction_rx.try_recv()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_prediction_for_not_configured_als_profile() -> Result<(), Box<dyn Error>> {
+        let (mut controller, _, prediction_rx) = setup()?;
+
+        controller.process(ALS_UNKNOWN);
+        assert!(prediction_rx.try_recv().is_err());
+
+        Ok(())
+    }
+}
@@ -1,57 +1,29 @@
-use super::{
-    Controller as _, INITIAL_TIMEOUT_SECS, NEXT_ALS_COOLDOWN_RESET, PENDING_COOLDOWN_RESET,
-};
+use super::{Als, Controller as _, Cooldown};
+use crate::config::{self, ManualMode};
 use crate::predictor::data::Entry;
 use itertools::Itertools;
 use std::{
     collections::HashMap,
     sync::mpsc::{Receiver, Sender},
-    time::Duration,
 };
 
 pub struct Controller {
     prediction_tx: Sender<u64>,
     user_rx: Receiver<u64>,
-    als_rx: Receiver<String>,
     last_brightness: Option<u64>,
     thresholds: HashMap<String, HashMap<u8, u64>>,
+    mode: ManualMode,
+    max_brightness: u64,
     pre_reduction_brightness: Option<u64>,
-    pending_cooldown: u8,
-    last_als: Option<String>,
-    next_als: Option<String>,
-    next_als_cooldown: u8,
+    /// Offset from the absolute brightness that user set in the given ALS profile.
+    offset: Option<(String, i64)>,
+    pending_cooldown: Cooldown,
+    als: Als,
 }
 
 impl super::Controller for Controller {
     fn adjust(&mut self, luma: u8) {
-        if self.last_als.is_none() {
-            // ALS controller is expected to send the initial value on this channel asap
-            self.last_als = self
-                .als_rx
-                .recv_timeout(Duration::from_secs(INITIAL_TIMEOUT_SECS))
-                .map_or_else(
-                    |e| panic!("Did not receive initial ALS value in time: {e:?}"),
-                    Some,
-                );
-        }
-
-        match self.als_rx.try_iter().last() {
-            new_als @ Some(_) if self.next_als != new_als => {
-                self.next_als = new_als;
-                self.next_als_cooldown = NEXT_ALS_COOLDOWN_RESET;
-            }
-            _ if self.next_als_cooldown > 1 => {
-                self.next_als_cooldown -= 1;
-            }
-            _ if self.next_als_cooldown == 1 => {
-                self.next_als_cooldown = 0;
-                self.last_als = self.next_als.take();
-            }
-            _ => {}
-        }
-
-        let lux = &self.last_als.clone().expect("ALS value must be known");
-
+        let lux = &self.als.profile();
         self.process(lux, luma);
     }
 }
@@ -62,18 +34,21 @@ impl Controller {
         user_rx: Receiver<u64>,
         als_rx: Receiver<String>,
         thresholds: HashMap<String, HashMap<u8, u64>>,
+        mode: ManualMode,
+        max_brightness: u64,
+        cooldown: config::Cooldown,
     ) -> Self {
         Self {
             prediction_tx,
             user_rx,
-            als_rx,
             last_brightness: None,
             thresholds,
+            mode,
+            max_brightness,
             pre_reduction_brightness: None,
-            pending_cooldown: 0,
-            last_als: None,
-            next_als: None,
-            next_als_cooldown: 0,
+            offset: None,
+            pending_cooldown: Cooldown::new(cooldown.adjustment),
+            als: Als::new(als_rx, cooldown.als),
         }
     }
 
@@ -86,7 +61,10 @@ impl Controller {
                 .last()
                 .or_else(|| panic!("Did not receive initial brightness value"));
 
-            self.process_brightness_change(self.last_brightness.unwrap(), lux, luma);
+            // Absolute brightness doesn't depend on the brightness wluma was started with
+            if self.mode == ManualMode::Reduction {
+                self.process_brightness_change(self.last_brightness.unwrap(), lux, luma);
+            }
         }
 
         let current_brightness = self
@@ -98,21 +76,37 @@ impl Controller {
 
         if self.last_brightness != Some(current_brightness) {
             self.process_brightness_change(current_brightness, lux, luma);
-            self.pending_cooldown = PENDING_COOLDOWN_RESET;
-        } else if self.pending_cooldown > 0 {
-            self.pending_cooldown -= 1;
-        } else {
+            self.pending_cooldown.reset();
+        } else if !self.pending_cooldown.active() {
             self.predict(current_brightness, lux, luma);
         }
     }
 
     fn predict(&mut self, current_brightness: u64, lux: &str, luma: u8) {
-        let brightness_reduction = self.get_brightness_reduction(current_brightness, lux, luma);
-
-        let prediction = self
-            .pre_reduction_brightness
-            .expect("Pre-reduction brightness value must be known by now")
-            .saturating_sub(brightness_reduction);
+        let prediction = match self.mode {
+            ManualMode::Reduction => {
+                let brightness_reduction =
+                    self.get_brightness_reduction(current_brightness, lux, luma);
+
+                self.pre_reduction_brightness
+                    .expect("Pre-reduction brightness value must be known by now")
+                    .saturating_sub(brightness_reduction)
+            }
+            ManualMode::Absolute => {
+                if self
+                    .offset
+                    .as_ref()
+                    .is_some_and(|(offset_lux, _)| offset_lux != lux)
+                {
+                    self.offset = None;
+                }
+                let Some(brightness) = self.get_absolute_brightness(lux, luma) else {
+                    return;
+                };
+                let offset = self.offset.as_ref().map_or(0, |(_, offset)| *offset);
+                (brightness + offset).clamp(0, self.max_brightness as i64) as u64
+            }
+        };
 
         log::trace!("Prediction: {} (lux: {}, luma: {})", prediction, lux, luma);
         self.prediction_tx
@@ -120,27 +114,49 @@ impl Controller {
             .expect("Unable to send predicted brightness value, channel is dead");
     }
 
-    fn get_brightness_reduction(&mut self, current_brightness: u64, lux: &str, luma: u8) -> u64 {
-        let entries = self
-            .thresholds
+    fn entries(&self, lux: &str) -> Vec<Entry> {
+        self.thresholds
             .get(lux)
             .unwrap_or(&HashMap::new())
             .iter()
-            .map(|(&luma, &percentage_reduction)| Entry {
+            .map(|(&luma, &percentage)| Entry {
                 lux: lux.to_string(),
                 luma,
-                brightness: percentage_reduction,
+                brightness: percentage as f64,
+                time: None,
+                learned_at: None,
+                uses: 0,
+                app: None,
             })
-            .collect_vec();
+            .collect_vec()
+    }
 
-        let brightness_reduction = self.interpolate(&entries, lux, luma);
+    fn get_brightness_reduction(&mut self, current_brightness: u64, lux: &str, luma: u8) -> u64 {
+        let entries = self.entries(lux);
+        let brightness_reduction = self.interpolate(&entries, lux, luma, None, None);
 
-        (current_brightness as f64 * brightness_reduction.unwrap_or(0) as f64 / 100.) as u64
+        (current_brightness as f64 * brightness_reduction.unwrap_or(0.) / 100.) as u64
+    }
+
+    /// Brightness that thresholds of the ALS profile define for the given luma, if there are any.
+    fn get_absolute_brightness(&self, lux: &str, luma: u8) -> Option<i64> {
+        let percentage = self.interpolate(&self.entries(lux), lux, luma, None, None)?;
+        Some((self.max_brightness as f64 * percentage / 100.).round() as i64)
     }
 
     fn process_brightness_change(&mut self, new_brightness: u64, lux: &str, luma: u8) {
-        let brightness_reduction = self.get_brightness_reduction(new_brightness, lux, luma);
-        self.pre_reduction_brightness = Some(new_brightness + brightness_reduction);
+        match self.mode {
+            ManualMode::Reduction => {
+                let brightness_reduction = self.get_brightness_reduction(new_brightness, lux, luma);
+                self.pre_reduction_brightness = Some(new_brightness + brightness_reduction);
+            }
+            // User change shifts the brightness until the ALS profile changes
+            ManualMode::Absolute => {
+                self.offset = self
+                    .get_absolute_brightness(lux, luma)
+                    .map(|brightness| (lux.to_string(), new_brightness as i64 - brightness));
+            }
+        }
         self.last_brightness = Some(new_brightness);
     }
 }
@@ -151,6 +167,8 @@ mod tests {
     use std::collections::HashMap;
     use std::error::Error;
     use std::sync::mpsc;
+    use std::time::Duration;
+    use std::time::Instant;
 
     const ALS_UNKNOWN: &str = "not-configured-threshold";
     const ALS_DIM: &str = "dim";
@@ -169,7 +187,19 @@ mod tests {
         .into_iter()
         .collect();
 
-        let controller = Controller::new(prediction_tx, user_rx, als_rx, thresholds);
+        let cooldown = config::Cooldown {
+            adjustment: Duration::from_secs(60),
+            als: Duration::from_secs(60),
+        };
+        let controller = Controller::new(
+            prediction_tx,
+            user_rx,
+            als_rx,
+            thresholds,
+            ManualMode::Reduction,
+            200,
+            cooldown,
+        );
         Ok((controller, user_tx, prediction_rx))
     }
 
@@ -244,18 +274,46 @@ mod tests {
 
         // Consequent user change causes prediction only after cooldown
         user_tx.send(123)?;
-        for i in 0..=PENDING_COOLDOWN_RESET {
+        for i in 0..=15 {
             // User doesn't change brightness anymore, so even if lux or luma change, we are in cooldown period
             controller.process(ALS_DIM, i);
-            assert_eq!(PENDING_COOLDOWN_RESET - i, controller.pending_cooldown);
+            assert!(controller.pending_cooldown.active());
             assert!(prediction_rx.try_recv().is_err());
         }
 
-        // One final call will generate the actual prediction
+        // Once the cooldown is over, the next call will generate the actual prediction
+        controller.pending_cooldown.until = Some(Instant::now());
         controller.process(ALS_DIM, 50);
-        assert_eq!(0, controller.pending_cooldown);
         assert_eq!(87, prediction_rx.recv()?);
 
         Ok(())
     }
+
+    #[test]
+    fn test_absolute_brightness_with_offset_by_user() -> Result<(), Box<dyn Error>> {
+        let (mut controller, user_tx, prediction_rx) = setup()?;
+        controller.mode = ManualMode::Absolute;
+
+        // Brightness follows the thresholds regardless of the initial brightness
+        controller.process(ALS_DIM, 50);
+        assert_eq!(60, prediction_rx.recv()?);
+        controller.process(ALS_DIM, 100);
+        assert_eq!(120, prediction_rx.recv()?);
+
+        // User change shifts the thresholds...
+        user_tx.send(100)?;
+        controller.process(ALS_DIM, 100);
+        assert!(prediction_rx.try_recv().is_err());
+        controller.pending_cooldown.until = Some(Instant::now());
+        controller.process(ALS_DIM, 50);
+        assert_eq!(40, prediction_rx.recv()?);
+
+        // ... until the ALS profile changes
+        controller.process(ALS_UNKNOWN, 50);
+        assert!(prediction_rx.try_recv().is_err());
+        controller.process(ALS_DIM, 50);
+        assert_eq!(60, prediction_rx.recv()?);
+
+        Ok(())
+    }
 }
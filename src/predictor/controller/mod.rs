@@ -1,55 +1,277 @@
 use super::data::Entry;
-use itertools::Itertools;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
 
 pub mod adaptive;
+pub mod levels;
 pub mod manual;
 
 const INITIAL_TIMEOUT_SECS: u64 = 5;
-const PENDING_COOLDOWN_RESET: u8 = 15;
-const NEXT_ALS_COOLDOWN_RESET: u8 = 15;
+
+/// How much one hour of difference in time of day matters, compared to the difference in luma.
+const LUMA_PER_HOUR: f64 = 5.;
+/// Entries without time of day are considered to be learned at an average distance from now.
+const UNKNOWN_TIME_DISTANCE_HOURS: f64 = 6.;
+
+/// Distance between two times of day (in minutes since midnight), wrapping around midnight.
+pub(super) fn time_distance(a: u16, b: u16) -> u16 {
+    let distance = a.abs_diff(b) % (24 * 60);
+    distance.min(24 * 60 - distance)
+}
+
+/// Distance of an entry from the current conditions, in luma and also in time of day, if it is given.
+fn distance(entry: &Entry, luma: u8, time: Option<u16>) -> f64 {
+    let luma_distance = (luma as f64 - entry.luma as f64).abs();
+    match time {
+        Some(time) => {
+            let hours = entry.time.map_or(UNKNOWN_TIME_DISTANCE_HOURS, |t| {
+                time_distance(time, t) as f64 / 60.
+            });
+            luma_distance.hypot(hours * LUMA_PER_HOUR)
+        }
+        None => luma_distance,
+    }
+}
+
+/// Waits until something stops changing for a while,
+/// measured in time rather than in frames, as frames are captured at different rates.
+struct Cooldown {
+    duration: Duration,
+    until: Option<Instant>,
+}
+
+impl Cooldown {
+    fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            until: None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.until = Some(Instant::now() + self.duration);
+    }
+
+    fn active(&self) -> bool {
+        self.until.is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Whether the cooldown is over, which is only reported once after every reset.
+    fn finished(&mut self) -> bool {
+        match self.until {
+            Some(until) if Instant::now() >= until => {
+                self.until = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// ALS profile that is only switched to once the new one has lasted for a while,
+/// so that brief changes in light don't change brightness back and forth.
+struct Als {
+    rx: Receiver<String>,
+    current: Option<String>,
+    next: Option<String>,
+    cooldown: Cooldown,
+}
+
+impl Als {
+    fn new(rx: Receiver<String>, cooldown: Duration) -> Self {
+        Self {
+            rx,
+            current: None,
+            next: None,
+            cooldown: Cooldown::new(cooldown),
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Current ALS profile, waits for the initial one if it is not known yet.
+    fn profile(&mut self) -> String {
+        if self.current.is_none() {
+            // ALS controller is expected to send the initial value on this channel asap
+            self.current = self
+                .rx
+                .recv_timeout(Duration::from_secs(INITIAL_TIMEOUT_SECS))
+                .map_or_else(
+                    |e| panic!("Did not receive initial ALS value in time: {e:?}"),
+                    Some,
+                );
+        }
+
+        match self.rx.try_iter().last() {
+            new_als @ Some(_) if self.next != new_als => {
+                self.next = new_als;
+                self.cooldown.reset();
+            }
+            _ if self.cooldown.finished() => {
+                self.current = self.next.take();
+            }
+            _ => {}
+        }
+
+        self.current.clone().expect("ALS value must be known")
+    }
+}
 
 pub trait Controller {
     fn adjust(&mut self, luma: u8);
 
-    fn interpolate(&self, entries: &[Entry], lux: &str, luma: u8) -> Option<u64> {
-        let points = entries
+    /// Called whenever the output is connected to a monitor, before the first adjustment on it,
+    /// with the identity of the monitor if it is known.
+    fn identify(&mut self, _identity: Option<&str>) {}
+
+    /// Called whenever a different app gets focused on the output, if preferences are learned per app.
+    fn focus(&mut self, _app: Option<&str>) {}
+
+    /// How much an entry matters compared to others at the same distance.
+    fn weight(&self, _entry: &Entry) -> f64 {
+        1.
+    }
+
+    /// Inverse distance weighting of entries of the given ALS profile by their distance in luma,
+    /// and also in time of day, if it is given. Entries that match exactly are used as they are.
+    /// Only entries of the given app are used, or the global ones learned without an app if it is not given.
+    fn interpolate(
+        &self,
+        entries: &[Entry],
+        lux: &str,
+        luma: u8,
+        time: Option<u16>,
+        app: Option<&str>,
+    ) -> Option<f64> {
+        let (mut sum, mut total_weight) = (0., 0.);
+        let (mut exact_sum, mut exact_weight, mut exact_count) = (0., 0., 0);
+        let (mut closest_sum, mut closest_count, mut closest_distance) = (0., 0, f64::INFINITY);
+
+        for entry in entries
             .iter()
-            .filter(|e| e.lux == lux)
-            .map(|entry| {
-                let distance = (luma as f64 - entry.luma as f64).abs();
-                (entry.brightness as f64, distance)
-            })
-            .collect_vec();
+            .filter(|e| e.lux == lux && e.app.as_deref() == app)
+        {
+            let distance = distance(entry, luma, time);
+            let weight = self.weight(entry);
+
+            if distance == 0. {
+                exact_sum += entry.brightness * weight;
+                exact_weight += weight;
+                exact_count += 1;
+            } else {
+                sum += entry.brightness * weight / distance;
+                total_weight += weight / distance;
+            }
 
-        if points.is_empty() {
-            return None;
+            // In case all entries are too old to matter, the closest ones are used
+            if distance < closest_distance {
+                (closest_sum, closest_count, closest_distance) = (0., 0, distance);
+            }
+            if distance == closest_distance {
+                closest_sum += entry.brightness;
+                closest_count += 1;
+            }
         }
 
-        let points = points
-            .iter()
-            .enumerate()
-            .map(|(i, p)| {
-                let other_distances: f64 = points[0..i]
-                    .iter()
-                    .chain(&points[i + 1..])
-                    .map(|p| p.1)
-                    .product();
-                (p.0, p.1, other_distances)
-            })
-            .collect_vec();
-
-        let distance_denominator: f64 = points
-            .iter()
-            .map(|p| p.1)
-            .combinations(points.len() - 1)
-            .map(|c| c.iter().product::<f64>())
-            .sum();
+        match (exact_count, closest_count) {
+            (_, 0) => None,
+            (1.., _) if exact_weight > 0. => Some(exact_sum / exact_weight),
+            (0, _) if total_weight > 0. => Some(sum / total_weight),
+            _ => Some(closest_sum / closest_count as f64),
+        }
+    }
+}
 
-        let prediction = points
-            .iter()
-            .map(|p| p.0 * p.2 / distance_denominator)
-            .sum::<f64>() as u64;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fake;
+    impl Controller for Fake {
+        fn adjust(&mut self, _luma: u8) {}
+    }
 
-        Some(prediction)
+    #[test]
+    fn test_cooldown_is_measured_in_time() {
+        let mut cooldown = Cooldown::new(Duration::from_secs(60));
+        assert!(!cooldown.active());
+        assert!(!cooldown.finished());
+
+        cooldown.reset();
+        assert!(cooldown.active());
+        assert!(!cooldown.finished());
+
+        cooldown.until = Some(Instant::now() - Duration::from_millis(1));
+        assert!(!cooldown.active());
+        assert!(cooldown.finished());
+        assert!(!cooldown.finished());
+    }
+
+    #[test]
+    fn test_time_distance_wraps_around_midnight() {
+        assert_eq!(0, time_distance(600, 600));
+        assert_eq!(90, time_distance(600, 690));
+        assert_eq!(60, time_distance(23 * 60 + 30, 30));
+        assert_eq!(12 * 60, time_distance(0, 12 * 60));
+    }
+
+    #[test]
+    fn test_interpolate_several_exact_matches() {
+        let entries = vec![
+            Entry::new("dim", 20, 0.25).with_time(Some(60)),
+            Entry::new("dim", 20, 0.75).with_time(Some(600)),
+            Entry::new("dim", 80, 1.),
+        ];
+
+        assert_eq!(Some(0.5), Fake.interpolate(&entries, "dim", 20, None, None));
+    }
+
+    #[test]
+    fn test_interpolate_entries_of_app() {
+        let entries = vec![
+            Entry::new("dim", 20, 0.2),
+            Entry::new("dim", 20, 0.6).with_app(Some("mpv".to_string())),
+        ];
+
+        let mpv = Fake.interpolate(&entries, "dim", 20, None, Some("mpv"));
+        assert_eq!(Some(0.6), mpv);
+        assert_eq!(
+            None,
+            Fake.interpolate(&entries, "dim", 20, None, Some("gimp"))
+        );
+        // Preferences of apps don't leak into the global ones
+        assert_eq!(Some(0.2), Fake.interpolate(&entries, "dim", 20, None, None));
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `just bench`"]
+    fn bench_interpolate_hundreds_of_entries() {
+        let iterations = 10_000;
+        for count in [10, 100, 500, 1000] {
+            let entries = (0..count)
+                .map(|i| {
+                    Entry::new("dim", (i % 101) as u8, (i % 101) as f64 / 100.)
+                        .with_time(Some((i * 7 % (24 * 60)) as u16))
+                })
+                .collect::<Vec<_>>();
+
+            let start = std::time::Instant::now();
+            for i in 0..iterations {
+                let prediction =
+                    Fake.interpolate(&entries, "dim", (i % 101) as u8, Some(600), None);
+                assert!(prediction.is_some_and(f64::is_finite));
+            }
+
+            // Predictions are made several times per second, for every output
+            let per_prediction = start.elapsed() / iterations;
+            assert!(
+                per_prediction < Duration::from_millis(1),
+                "{} entries took {:?} per prediction",
+                count,
+                per_prediction
+            );
+        }
     }
 }
@@ -1,72 +1,998 @@
+use super::controller::time_distance;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
-use std::fs::{File, OpenOptions};
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Version of the data format, increased whenever existing files need to be migrated.
+pub const DATA_VERSION: u64 = 1;
+/// How many previous versions of each data file are kept.
+const BACKUP_COUNT: usize = 3;
+/// Entries learned within this many minutes of each other are considered to be the same time of day.
+const SAME_TIME_OF_DAY_MINUTES: u16 = 120;
+/// How many of the most recently learned entries can be undone.
+const JOURNAL_LENGTH: usize = 20;
+/// How many adjustments can wait to be confirmed by a similar one before they are learned.
+const UNCONFIRMED_LENGTH: usize = 20;
+/// Adjustments confirm each other when they differ by no more than this in luma and in brightness.
+pub const SIMILAR_LUMA: u8 = 10;
+const SIMILAR_BRIGHTNESS: f64 = 0.1;
 
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Data {
+    pub version: u64,
     pub output_name: String,
+    /// Manufacturer, model and serial number of the monitor, when the data is stored by its identity.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    /// Maximal brightness of the device the data was learned on, to notice when it changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_brightness: Option<u64>,
     pub entries: Vec<Entry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub journal: Vec<Learned>,
+    /// Adjustments that are not learned until a similar one confirms them, they are not used for predictions.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unconfirmed: Vec<Entry>,
+    /// Data that couldn't be read must not be overwritten, so that user can recover it.
+    #[serde(skip)]
+    pub read_only: bool,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone)]
+/// Learned brightness is a fraction of the maximal brightness (between 0 and 1),
+/// so that the data remains valid when the driver or the monitor changes.
+/// Time of day (in minutes since midnight) and time of learning (as a unix timestamp)
+/// are only recorded when enabled in the config.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct Entry {
     pub lux: String,
     pub luma: u8,
-    pub brightness: u64,
+    pub brightness: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub learned_at: Option<u64>,
+    /// How many times the entry was the closest one to a new prediction.
+    #[serde(default)]
+    pub uses: u64,
+    /// App that was focused when the entry was learned, if preferences are learned per app.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+}
+
+// Brightness is never NaN, so entries can be compared and hashed by their exact values
+impl Eq for Entry {}
+
+impl Hash for Entry {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.lux.hash(state);
+        self.luma.hash(state);
+        self.brightness.to_bits().hash(state);
+        self.time.hash(state);
+        self.learned_at.hash(state);
+        self.uses.hash(state);
+        self.app.hash(state);
+    }
+}
+
+/// Change of learned data (at a unix timestamp), so that it can be undone: an entry that was learned
+/// or several that were imported, along with the ones they replaced, or entries that were forgotten.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Learned {
+    pub at: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<Entry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replaced: Vec<Entry>,
+}
+
+/// Data stored before versioning was introduced, with brightness in raw device units.
+#[derive(Deserialize)]
+struct LegacyData {
+    output_name: String,
+    entries: Vec<LegacyEntry>,
+}
+
+#[derive(Deserialize)]
+struct LegacyEntry {
+    lux: String,
+    luma: u8,
+    brightness: u64,
+}
+
+impl LegacyData {
+    fn migrate(self, max_brightness: u64) -> Data {
+        Data {
+            entries: self
+                .entries
+                .into_iter()
+                .map(|e| Entry::new(&e.lux, e.luma, fraction(e.brightness, max_brightness)))
+                .collect(),
+            max_brightness: Some(max_brightness),
+            ..Data::new(&self.output_name)
+        }
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+/// Converts raw brightness into a fraction of the maximal brightness.
+pub fn fraction(brightness: u64, max_brightness: u64) -> f64 {
+    (brightness as f64 / max_brightness.max(1) as f64).clamp(0., 1.)
+}
+
+/// Converts a fraction of the maximal brightness back into raw brightness.
+pub fn raw(fraction: f64, max_brightness: u64) -> u64 {
+    (fraction.clamp(0., 1.) * max_brightness as f64).round() as u64
+}
+
+#[derive(Debug)]
+enum ParseError {
+    /// File was written by a newer version of wluma
+    Unsupported(u64),
+    /// File was written by an older version of wluma, and can only be migrated by the daemon
+    Unmigrated,
+    Corrupt(Box<dyn Error>),
+}
+
+impl<E: Into<Box<dyn Error>>> From<E> for ParseError {
+    fn from(err: E) -> Self {
+        Self::Corrupt(err.into())
+    }
+}
+
+/// Path next to the given one, with a suffix appended to the file name.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn backup_path(path: &Path, index: usize) -> PathBuf {
+    sibling(path, &format!(".{}", index))
 }
 
 impl Data {
     pub fn new(output_name: &str) -> Self {
         Self {
+            version: DATA_VERSION,
             output_name: output_name.to_string(),
+            identity: None,
+            max_brightness: None,
             entries: Vec::default(),
+            journal: Vec::default(),
+            unconfirmed: Vec::default(),
+            read_only: false,
         }
     }
 
-    pub fn load(output_name: &str) -> Self {
-        Self::path(output_name)
-            .ok()
-            .and_then(|path| Self::read_file(path).ok())
-            .and_then(|file| serde_yaml::from_reader(file).ok())
-            .unwrap_or_else(|| Self::new(output_name))
+    /// Loads learned data, files stored in an older format are migrated once,
+    /// assuming they were learned on a device with the given maximal brightness.
+    pub fn load(output_name: &str, max_brightness: u64) -> Self {
+        match Self::path(output_name) {
+            Ok(path) => Self::load_from(&path, output_name, max_brightness),
+            Err(err) => {
+                log::error!(
+                    "Unable to access learned data of '{}', it will not be saved: {}",
+                    output_name,
+                    err
+                );
+                Self::read_only(output_name)
+            }
+        }
     }
 
-    pub fn save(&self) -> Result<(), Box<dyn Error>> {
-        Ok(serde_yaml::to_writer(self.write_file()?, self)?)
+    fn load_from(path: &Path, output_name: &str, max_brightness: u64) -> Self {
+        Self::read_from(path, output_name, max_brightness).with_max_brightness(max_brightness)
     }
 
-    fn read_file(path: PathBuf) -> Result<File, Box<dyn Error>> {
-        Ok(OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(false)
-            .read(true)
-            .open(path)?)
+    /// Records the maximal brightness of the device, warning when the data was learned with another.
+    fn with_max_brightness(self, max_brightness: u64) -> Self {
+        match self.max_brightness {
+            Some(learned) if learned != max_brightness => log::warn!(
+                "Learned data of '{}' was learned with maximal brightness {}, but the device now reports {}, predictions might be off until they are relearned",
+                self.output_name,
+                learned,
+                max_brightness
+            ),
+            _ => {}
+        }
+
+        Self {
+            max_brightness: Some(max_brightness),
+            ..self
+        }
+    }
+
+    fn read_from(path: &Path, output_name: &str, max_brightness: u64) -> Self {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Self::new(output_name),
+            Err(err) => {
+                log::error!(
+                    "Unable to read learned data from '{}', it will not be overwritten: {}",
+                    path.display(),
+                    err
+                );
+                return Self::read_only(output_name);
+            }
+        };
+
+        match Self::parse(&content, output_name, Some(max_brightness)) {
+            Ok((data, migrated)) => {
+                if migrated {
+                    log::info!(
+                        "Migrating learned data of '{}' to version {}",
+                        output_name,
+                        DATA_VERSION
+                    );
+                    if let Err(err) = data.save_to(path) {
+                        log::warn!("Unable to save migrated data: {}", err);
+                    }
+                }
+                data
+            }
+            Err(ParseError::Unsupported(version)) => {
+                log::error!(
+                    "Learned data in '{}' has version {}, which is only supported by a newer wluma, it will not be overwritten",
+                    path.display(),
+                    version
+                );
+                Self::read_only(output_name)
+            }
+            Err(ParseError::Corrupt(err)) => Self::recover(path, output_name, max_brightness, err),
+            Err(ParseError::Unmigrated) => unreachable!("Maximal brightness is known"),
+        }
+    }
+
+    /// Preserves a corrupt file and falls back to the latest backup that can be read.
+    fn recover(path: &Path, output_name: &str, max_brightness: u64, err: Box<dyn Error>) -> Self {
+        // Data that got corrupt before is preserved too
+        let corrupt_path = (0..)
+            .map(|index| match index {
+                0 => sibling(path, ".corrupt"),
+                _ => sibling(path, &format!(".corrupt.{}", index)),
+            })
+            .find(|corrupt_path| !corrupt_path.exists())
+            .expect("There is always a free name for corrupt data");
+        log::error!(
+            "!!! Learned data in '{}' is corrupt ({}), it is preserved as '{}' !!!",
+            path.display(),
+            err,
+            corrupt_path.display()
+        );
+
+        if let Err(err) = fs::rename(path, &corrupt_path) {
+            log::error!(
+                "Unable to preserve corrupt data, it will not be overwritten: {}",
+                err
+            );
+            return Self::read_only(output_name);
+        }
+
+        let backup = (1..=BACKUP_COUNT).find_map(|index| {
+            let backup_path = backup_path(path, index);
+            let content = fs::read_to_string(&backup_path).ok()?;
+            let (data, _) = Self::parse(&content, output_name, Some(max_brightness)).ok()?;
+            Some((backup_path, data))
+        });
+
+        match backup {
+            Some((backup_path, data)) => {
+                log::warn!(
+                    "Recovered learned data of '{}' from '{}'",
+                    output_name,
+                    backup_path.display()
+                );
+                data
+            }
+            None => {
+                log::warn!(
+                    "No usable backup of '{}' was found, learning starts from scratch",
+                    output_name
+                );
+                Self::new(output_name)
+            }
+        }
+    }
+
+    /// Name under which data of the monitor with the given identity is stored.
+    pub fn identity_name(identity: &str) -> String {
+        identity.replace(['/', '\0'], "_")
+    }
+
+    /// Copies data stored under the config name of an output as a starting point for a monitor
+    /// that is now stored by its identity, unless the monitor has its own data already.
+    pub fn adopt(
+        from: &str,
+        into: &str,
+        identity: &str,
+        max_brightness: u64,
+    ) -> Result<bool, Box<dyn Error>> {
+        let _lock = Self::lock(into)?;
+        Self::adopt_from(
+            &Self::path(from)?,
+            &Self::path(into)?,
+            into,
+            identity,
+            max_brightness,
+        )
+    }
+
+    fn adopt_from(
+        from: &Path,
+        into: &Path,
+        name: &str,
+        identity: &str,
+        max_brightness: u64,
+    ) -> Result<bool, Box<dyn Error>> {
+        if into.exists() || !from.exists() {
+            return Ok(false);
+        }
+
+        let data = Self {
+            output_name: name.to_string(),
+            identity: Some(identity.to_string()),
+            ..Self::load_from(from, name, max_brightness)
+        };
+        data.save_to(into)?;
+        Ok(true)
+    }
+
+    /// Adds a new entry, replacing the ones it contradicts within the same environment,
+    /// returns the replaced entries.
+    pub fn insert(&mut self, new: Entry) -> Vec<Entry> {
+        let (entries, replaced): (Vec<_>, Vec<_>) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|entry| {
+                // Preferences at distant times of day don't contradict each other
+                let different_time = match (entry.time, new.time) {
+                    (Some(a), Some(b)) => time_distance(a, b) > SAME_TIME_OF_DAY_MINUTES,
+                    _ => false,
+                };
+                // Nor do preferences for different apps
+                let different_env = entry.lux != new.lux || entry.app != new.app || different_time;
+
+                let same_env_darker_screen =
+                    !different_env && entry.luma < new.luma && entry.brightness >= new.brightness;
+
+                let same_env_brighter_screen =
+                    !different_env && entry.luma > new.luma && entry.brightness <= new.brightness;
+
+                different_env || same_env_darker_screen || same_env_brighter_screen
+            });
+
+        self.entries = entries;
+        self.entries.push(new);
+        self.sort();
+        replaced
+    }
+
+    fn sort(&mut self) {
+        self.entries.sort_unstable_by(|x, y| {
+            x.lux
+                .cmp(&y.lux)
+                .then(x.luma.cmp(&y.luma))
+                .then(x.time.cmp(&y.time))
+                .then(x.app.cmp(&y.app))
+        });
+    }
+
+    /// Removes entries of other profiles (ordered from the darkest to the brightest) that would make
+    /// brightness decrease as ambient light increases, i.e. a darker environment with the same or brighter
+    /// screen contents and a brighter screen, or a brighter environment with the same or darker screen
+    /// contents and a dimmer screen. Returns the removed entries.
+    pub fn prune_contradictions(&mut self, profiles: &[String], new: &Entry) -> Vec<Entry> {
+        let Some(new_index) = profiles.iter().position(|p| *p == new.lux) else {
+            return vec![];
+        };
+
+        let output_name = &self.output_name;
+        let (entries, contradicting) =
+            std::mem::take(&mut self.entries)
+                .into_iter()
+                .partition(|entry| {
+                    let contradicts = match profiles.iter().position(|p| *p == entry.lux) {
+                        _ if entry.app != new.app => false,
+                        Some(index) if index < new_index => {
+                            entry.luma >= new.luma && entry.brightness > new.brightness
+                        }
+                        Some(index) if index > new_index => {
+                            entry.luma <= new.luma && entry.brightness < new.brightness
+                        }
+                        _ => false,
+                    };
+
+                    if contradicts {
+                        log::info!(
+                        "[{}] Forgetting {:?}, as it contradicts {:?} in a different ambient light",
+                        output_name,
+                        entry,
+                        new
+                    );
+                    }
+                    !contradicts
+                });
+
+        self.entries = entries;
+        contradicting
+    }
+
+    /// Adds entries one after another, replacing the ones they contradict, and remembers them
+    /// as a single change, so that they can be undone together. With the profiles ordered
+    /// from the darkest to the brightest, contradicting entries of other profiles are replaced too.
+    pub fn learn(&mut self, at: u64, entries: Vec<Entry>, monotonic_profiles: Option<&[String]>) {
+        let (mut added, mut replaced) = (vec![], vec![]);
+        for entry in entries {
+            let mut replacing = match monotonic_profiles {
+                Some(profiles) => self.prune_contradictions(profiles, &entry),
+                None => vec![],
+            };
+            replacing.extend(self.insert(entry.clone()));
+
+            // Entries that only existed as a part of this change have nothing to be restored
+            for old in replacing {
+                match added.iter().position(|e| *e == old) {
+                    Some(index) => drop(added.remove(index)),
+                    None => replaced.push(old),
+                }
+            }
+            added.push(entry);
+        }
+        self.record(at, added, replaced);
+    }
+
+    /// Removes matching entries and remembers them, so that they can be restored later,
+    /// returns how many were removed.
+    pub fn forget(&mut self, at: u64, matches: impl Fn(&Entry) -> bool) -> usize {
+        let (forgotten, entries): (Vec<_>, Vec<_>) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(matches);
+        self.entries = entries;
+
+        let count = forgotten.len();
+        if count > 0 {
+            self.record(at, vec![], forgotten);
+        }
+        count
+    }
+
+    fn record(&mut self, at: u64, entries: Vec<Entry>, replaced: Vec<Entry>) {
+        self.journal.push(Learned {
+            at,
+            entries,
+            replaced,
+        });
+        let excess = self.journal.len().saturating_sub(JOURNAL_LENGTH);
+        self.journal.drain(..excess);
+    }
+
+    /// Undoes the most recent changes: forgets the entries they added and restores the ones
+    /// they replaced or forgot, returns how many were undone.
+    pub fn undo(&mut self, count: usize) -> usize {
+        let count = count.min(self.journal.len());
+        for learned in self
+            .journal
+            .split_off(self.journal.len() - count)
+            .into_iter()
+            .rev()
+        {
+            for added in &learned.entries {
+                // The entry could have been used since then, or started aging once decay got enabled
+                let same = |entry: &Entry| {
+                    entry.lux == added.lux
+                        && entry.luma == added.luma
+                        && entry.brightness == added.brightness
+                        && entry.time == added.time
+                        && entry.app == added.app
+                };
+                if let Some(index) = self.entries.iter().position(same) {
+                    self.entries.remove(index);
+                }
+            }
+            self.entries.extend(learned.replaced);
+        }
+        self.sort();
+        count
+    }
+
+    /// Whether a similar adjustment was made in the same environment before, which is then no longer waiting,
+    /// otherwise the adjustment waits to be confirmed itself.
+    pub fn confirm(&mut self, adjustment: &Entry) -> bool {
+        let similar = self.unconfirmed.iter().position(|entry| {
+            let same_time = match (entry.time, adjustment.time) {
+                (Some(a), Some(b)) => time_distance(a, b) <= SAME_TIME_OF_DAY_MINUTES,
+                _ => true,
+            };
+            entry.lux == adjustment.lux
+                && entry.app == adjustment.app
+                && same_time
+                && entry.luma.abs_diff(adjustment.luma) <= SIMILAR_LUMA
+                && (entry.brightness - adjustment.brightness).abs() <= SIMILAR_BRIGHTNESS
+        });
+
+        match similar {
+            Some(index) => {
+                self.unconfirmed.remove(index);
+                true
+            }
+            None => {
+                self.unconfirmed.push(adjustment.clone());
+                let excess = self.unconfirmed.len().saturating_sub(UNCONFIRMED_LENGTH);
+                self.unconfirmed.drain(..excess);
+                false
+            }
+        }
+    }
+
+    /// Undoes everything learned after the given unix timestamp.
+    pub fn revert_to(&mut self, at: u64) -> usize {
+        let count = self
+            .journal
+            .iter()
+            .rev()
+            .take_while(|learned| learned.at > at)
+            .count();
+        self.undo(count)
+    }
+
+    fn read_only(output_name: &str) -> Self {
+        Self {
+            read_only: true,
+            ..Self::new(output_name)
+        }
     }
 
-    fn write_file(&self) -> Result<File, Box<dyn Error>> {
-        let path = Self::path(&self.output_name).unwrap();
-        Ok(OpenOptions::new()
+    /// Reads learned data as it is stored, without migrating or recovering it.
+    pub fn read(output_name: &str) -> Result<Self, Box<dyn Error>> {
+        let path = Self::path(output_name)?;
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::new(output_name)),
+            Err(err) => Err(err)?,
+        };
+
+        match Self::parse(&content, output_name, None) {
+            Ok((data, _)) => Ok(data),
+            Err(ParseError::Unsupported(version)) => Err(format!(
+                "'{}' has version {}, which is only supported by a newer wluma",
+                path.display(),
+                version
+            ))?,
+            Err(ParseError::Unmigrated) => Err(format!(
+                "'{}' was stored by an older wluma, run wluma once to migrate it",
+                path.display()
+            ))?,
+            Err(ParseError::Corrupt(err)) => {
+                Err(format!("'{}' is corrupt: {}", path.display(), err))?
+            }
+        }
+    }
+
+    /// Safely changes stored data, even while wluma is running and learning.
+    pub fn update(output_name: &str, update: impl FnOnce(&mut Self)) -> Result<(), Box<dyn Error>> {
+        let _lock = Self::lock(output_name)?;
+        let mut data = Self::read(output_name)?;
+        update(&mut data);
+        data.save()
+    }
+
+    /// Exclusive access to the stored data, released when the returned file is closed.
+    pub fn lock(output_name: &str) -> Result<File, Box<dyn Error>> {
+        let file = File::options()
             .create(true)
+            .truncate(false)
             .write(true)
-            .truncate(true)
-            .open(path)?)
+            .open(sibling(&Self::path(output_name)?, ".lock"))?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            Err(io::Error::last_os_error())?;
+        }
+        Ok(file)
+    }
+
+    /// When the stored data was last changed, by wluma itself or by another process.
+    pub fn modified(output_name: &str) -> Option<SystemTime> {
+        fs::metadata(Self::path(output_name).ok()?)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Names of all outputs that have learned data stored.
+    pub fn output_names() -> Result<Vec<String>, Box<dyn Error>> {
+        let mut names = fs::read_dir(Self::dir()?)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                match path.extension()?.to_str()? {
+                    "yaml" => Some(path.file_stem()?.to_string_lossy().to_string()),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Parses stored data, also returns whether it had to be migrated from an older format,
+    /// which requires the maximal brightness of the device it was learned on.
+    fn parse(
+        content: &str,
+        output_name: &str,
+        max_brightness: Option<u64>,
+    ) -> Result<(Self, bool), ParseError> {
+        // Files were created empty, before anything was learned
+        if content.trim().is_empty() {
+            return Ok((Self::new(output_name), false));
+        }
+
+        let value = serde_yaml::from_str::<serde_yaml::Value>(content)?;
+        let version = match value.get("version") {
+            Some(version) => version.as_u64().ok_or("Invalid version")?,
+            None => 0,
+        };
+
+        match (version, max_brightness) {
+            (0, Some(max_brightness)) => Ok((
+                serde_yaml::from_value::<LegacyData>(value)?.migrate(max_brightness),
+                true,
+            )),
+            (0, None) => Err(ParseError::Unmigrated),
+            (DATA_VERSION, _) => Ok((serde_yaml::from_value(value)?, false)),
+            (version, _) => Err(ParseError::Unsupported(version)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        self.save_to(&Self::path(&self.output_name)?)
+    }
+
+    /// Replaces the file atomically, so that it is never left half-written,
+    /// and keeps a few previous versions of it as backups.
+    fn save_to(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if self.read_only {
+            Err(format!(
+                "Refusing to overwrite '{}', which could not be read",
+                path.display()
+            ))?;
+        }
+
+        let tmp_path = sibling(path, ".tmp");
+        let mut file = File::create(&tmp_path)?;
+        serde_yaml::to_writer(&mut file, self)?;
+        file.sync_all()?;
+
+        if path.exists() {
+            for index in (1..BACKUP_COUNT).rev() {
+                let backup = backup_path(path, index);
+                if backup.exists() {
+                    fs::rename(&backup, backup_path(path, index + 1))?;
+                }
+            }
+            fs::copy(path, backup_path(path, 1))?;
+        }
+
+        fs::rename(&tmp_path, path)?;
+        if let Some(dir) = path.parent() {
+            File::open(dir)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+
+    fn dir() -> Result<PathBuf, Box<dyn Error>> {
+        Ok(xdg::BaseDirectories::with_prefix("wluma")?.create_data_directory("")?)
     }
 
     fn path(output_name: &str) -> Result<PathBuf, Box<dyn Error>> {
-        Ok(xdg::BaseDirectories::with_prefix("wluma")?
-            .create_data_directory("")?
-            .join(format!("{:}.yaml", output_name)))
+        Ok(Self::dir()?.join(Self::file_name(output_name)?))
+    }
+
+    /// Names come from the config and from imported files, they must not lead out of the data directory.
+    fn file_name(output_name: &str) -> Result<String, Box<dyn Error>> {
+        if output_name.is_empty()
+            || output_name.starts_with('.')
+            || output_name.contains(['/', '\0'])
+        {
+            Err(format!(
+                "Invalid output name '{}', it must neither contain '/' nor start with '.'",
+                output_name
+            ))?;
+        }
+        Ok(format!("{}.yaml", output_name))
     }
 }
 
 impl Entry {
-    pub fn new(lux: &str, luma: u8, brightness: u64) -> Self {
+    pub fn new(lux: &str, luma: u8, brightness: f64) -> Self {
         Self {
             lux: lux.to_string(),
             luma,
             brightness,
+            time: None,
+            learned_at: None,
+            uses: 0,
+            app: None,
+        }
+    }
+
+    pub fn with_time(self, time: Option<u16>) -> Self {
+        Self { time, ..self }
+    }
+
+    pub fn with_app(self, app: Option<String>) -> Self {
+        Self { app, ..self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    fn data(brightness: f64) -> Data {
+        Data {
+            entries: vec![Entry::new("dim", 10, brightness)],
+            max_brightness: Some(100),
+            ..Data::new("eDP-1")
+        }
+    }
+
+    #[test]
+    fn test_parse_current_version() {
+        let data = data(0.25);
+        let content = serde_yaml::to_string(&data).unwrap();
+
+        assert_eq!(
+            (data, false),
+            Data::parse(&content, "eDP-1", Some(96000)).unwrap()
+        );
+        assert_eq!(
+            (Data::new("eDP-1"), false),
+            Data::parse("", "eDP-1", Some(96000)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_migrates_raw_brightness() {
+        let content = "\
+output_name: eDP-1
+entries:
+- lux: dim
+  luma: 10
+  brightness: 24000
+- lux: bright
+  luma: 80
+  brightness: 96000
+";
+
+        assert_eq!(
+            (
+                Data {
+                    entries: vec![Entry::new("dim", 10, 0.25), Entry::new("bright", 80, 1.)],
+                    max_brightness: Some(96000),
+                    ..Data::new("eDP-1")
+                },
+                true
+            ),
+            Data::parse(content, "eDP-1", Some(96000)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fraction_survives_different_max_brightness() {
+        let learned = fraction(48000, 96000);
+
+        assert_eq!(0.5, learned);
+        assert_eq!(8, raw(learned, 15));
+        assert_eq!(48000, raw(learned, 96000));
+        assert_eq!(15, raw(1.5, 15));
+    }
+
+    #[test]
+    fn test_save_keeps_rotating_backups() -> Result<(), Box<dyn Error>> {
+        let dir = TempDir::new("data-backups");
+        let path = dir.join("eDP-1.yaml");
+
+        for i in 1..=5 {
+            data(i as f64 / 10.).save_to(&path)?;
+        }
+
+        assert_eq!(data(0.5), Data::load_from(&path, "eDP-1", 100));
+        assert_eq!(
+            data(0.4),
+            Data::load_from(&backup_path(&path, 1), "eDP-1", 100)
+        );
+        assert_eq!(
+            data(0.2),
+            Data::load_from(&backup_path(&path, 3), "eDP-1", 100)
+        );
+        assert!(!backup_path(&path, 4).exists());
+        assert!(!sibling(&path, ".tmp").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_name_stays_in_data_dir() {
+        assert_eq!("eDP-1.yaml", Data::file_name("eDP-1").unwrap());
+        assert_eq!(
+            "Dell Inc. DELL P2415Q.yaml",
+            Data::file_name("Dell Inc. DELL P2415Q").unwrap()
+        );
+        assert!(Data::file_name("../../.config/wluma/config").is_err());
+        assert!(Data::file_name("..").is_err());
+        assert!(Data::file_name(".hidden").is_err());
+        assert!(Data::file_name("").is_err());
+    }
+
+    #[test]
+    fn test_load_records_changed_max_brightness() -> Result<(), Box<dyn Error>> {
+        let dir = TempDir::new("data-max-brightness");
+        let path = dir.join("eDP-1.yaml");
+        data(0.5).save_to(&path)?;
+
+        // Learned fractions are kept, but the new maximal brightness is what gets saved from now on
+        let loaded = Data::load_from(&path, "eDP-1", 96000);
+        assert_eq!(Some(96000), loaded.max_brightness);
+        assert_eq!(data(0.5).entries, loaded.entries);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_recovers_corrupt_data_from_backup() -> Result<(), Box<dyn Error>> {
+        let dir = TempDir::new("data-corrupt");
+        let path = dir.join("eDP-1.yaml");
+        data(0.1).save_to(&path)?;
+        data(0.2).save_to(&path)?;
+        fs::write(&path, "entries: [{ lux: dim, luma: 10")?;
+
+        assert_eq!(data(0.1), Data::load_from(&path, "eDP-1", 100));
+        assert_eq!(
+            "entries: [{ lux: dim, luma: 10",
+            fs::read_to_string(sibling(&path, ".corrupt"))?
+        );
+
+        // Data that got corrupt before is kept
+        fs::write(&path, "entries: [{ lux: dim")?;
+        assert_eq!(data(0.1), Data::load_from(&path, "eDP-1", 100));
+        assert_eq!(
+            "entries: [{ lux: dim, luma: 10",
+            fs::read_to_string(sibling(&path, ".corrupt"))?
+        );
+        assert_eq!(
+            "entries: [{ lux: dim",
+            fs::read_to_string(sibling(&path, ".corrupt.1"))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_refuses_to_overwrite_newer_version() -> Result<(), Box<dyn Error>> {
+        let dir = TempDir::new("data-newer");
+        let path = dir.join("eDP-1.yaml");
+        let content = "version: 99\noutput_name: eDP-1\nentries: []\n";
+        fs::write(&path, content)?;
+
+        let data = Data::load_from(&path, "eDP-1", 100);
+
+        assert!(data.read_only);
+        assert!(data.save_to(&path).is_err());
+        assert_eq!(content, fs::read_to_string(&path)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_adopt_data_stored_by_config_name() -> Result<(), Box<dyn Error>> {
+        let dir = TempDir::new("data-adopt");
+        let (path, home, office) = (
+            dir.join("eDP-1.yaml"),
+            dir.join("DEL DELL P2415Q 1.yaml"),
+            dir.join("DEL DELL U2720Q 2.yaml"),
+        );
+        data(0.25).save_to(&path)?;
+        Data {
+            identity: Some("DEL DELL U2720Q 2".to_string()),
+            ..data(0.75)
+        }
+        .save_to(&office)?;
+
+        // Monitors without their own data start from what was learned under the config name
+        assert!(Data::adopt_from(
+            &path,
+            &home,
+            "DEL DELL P2415Q 1",
+            "DEL DELL P2415Q 1",
+            100
+        )?);
+        assert!(!Data::adopt_from(
+            &path,
+            &office,
+            "DEL DELL U2720Q 2",
+            "DEL DELL U2720Q 2",
+            100
+        )?);
+
+        let adopted = Data::load_from(&home, "DEL DELL P2415Q 1", 100);
+        assert_eq!(Some("DEL DELL P2415Q 1".to_string()), adopted.identity);
+        assert_eq!(data(0.25).entries, adopted.entries);
+        assert_eq!(
+            0.75,
+            Data::load_from(&office, "DEL DELL U2720Q 2", 100).entries[0].brightness
+        );
+        assert!(path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_restores_replaced_entries() {
+        let mut data = Data::new("eDP-1");
+        let first = Entry::new("dim", 20, 0.25);
+        let second = Entry::new("dim", 20, 0.5);
+        let third = Entry::new("dim", 30, 0.25);
+        for (at, entry) in [(100, &first), (200, &second), (300, &third)] {
+            data.learn(at, vec![entry.clone()], None);
+        }
+        assert_eq!(vec![second.clone(), third.clone()], data.entries);
+
+        // Entries keep being used after they are learned
+        data.entries[0].uses = 5;
+
+        assert_eq!(1, data.revert_to(200));
+        assert_eq!(
+            vec![5],
+            data.entries.iter().map(|e| e.uses).collect::<Vec<_>>()
+        );
+        assert_eq!(2, data.undo(5));
+        assert_eq!(Vec::<Entry>::new(), data.entries);
+        assert!(data.journal.is_empty());
+    }
+
+    #[test]
+    fn test_undo_import_and_forget() {
+        let mut data = Data::new("eDP-1");
+        let learned = Entry::new("dim", 20, 0.25);
+        data.learn(100, vec![learned.clone()], None);
+
+        // Imported entries replace each other, but only the learned one is restored
+        let imported = vec![
+            Entry::new("dim", 20, 0.4),
+            Entry::new("dim", 20, 0.5),
+            Entry::new("bright", 80, 1.),
+        ];
+        data.learn(200, imported, None);
+        assert_eq!(2, data.entries.len());
+        assert_eq!(vec![learned.clone()], data.journal[1].replaced);
+
+        assert_eq!(1, data.forget(300, |entry| entry.lux == "bright"));
+        assert_eq!(0, data.forget(400, |entry| entry.lux == "dark"));
+        assert_eq!(vec![Entry::new("dim", 20, 0.5)], data.entries);
+
+        assert_eq!(1, data.undo(1));
+        assert_eq!(2, data.entries.len());
+        assert_eq!(1, data.undo(1));
+        assert_eq!(vec![learned], data.entries);
+    }
+
+    #[test]
+    fn test_journal_is_limited() {
+        let mut data = Data::new("eDP-1");
+        for i in 0..JOURNAL_LENGTH as u64 + 5 {
+            data.learn(i, vec![Entry::new("dim", 20, 0.5)], None);
         }
+
+        assert_eq!(JOURNAL_LENGTH, data.journal.len());
+        assert_eq!(5, data.journal[0].at);
     }
 }
@@ -1,3 +1,4 @@
+pub mod cli;
 pub mod controller;
 mod data;
 pub use controller::Controller;
@@ -0,0 +1,80 @@
+use crate::brightness::Event;
+use dbus::arg::prop_cast;
+use dbus::blocking::stdintf::org_freedesktop_dbus::{Properties, PropertiesPropertiesChanged};
+use dbus::blocking::Connection;
+use dbus::Message;
+use std::error::Error;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+const LOGIND_DESTINATION: &str = "org.freedesktop.login1";
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+const DBUS_TIMEOUT_MS: u64 = 1000;
+
+/// Session is inactive while logind reports it as idle or locked,
+/// which is when power management of desktop environments usually dims the screen.
+struct Hints {
+    idle: bool,
+    locked: bool,
+    event_txs: Vec<Sender<Event>>,
+}
+
+impl Hints {
+    fn update(&mut self, idle: Option<bool>, locked: Option<bool>) {
+        let was_inactive = self.idle || self.locked;
+        self.idle = idle.unwrap_or(self.idle);
+        self.locked = locked.unwrap_or(self.locked);
+
+        let inactive = self.idle || self.locked;
+        if inactive != was_inactive {
+            log::debug!(
+                "Session is {}",
+                if inactive { "inactive" } else { "active" }
+            );
+            for event_tx in &self.event_txs {
+                event_tx
+                    .send(Event::SessionInactive(inactive))
+                    .expect("Unable to send session state, channel is dead");
+            }
+        }
+    }
+}
+
+/// Keeps every output informed about the session state reported by logind,
+/// never returns unless there is an error.
+pub fn watch(event_txs: Vec<Sender<Event>>) -> Result<(), Box<dyn Error>> {
+    let connection = Connection::new_system()?;
+    let timeout = Duration::from_millis(DBUS_TIMEOUT_MS);
+
+    let (session,): (dbus::Path<'static>,) = connection
+        .with_proxy(LOGIND_DESTINATION, "/org/freedesktop/login1", timeout)
+        .method_call("org.freedesktop.login1.Manager", "GetSession", ("auto",))?;
+    let proxy = connection.with_proxy(LOGIND_DESTINATION, session, timeout);
+
+    let mut hints = Hints {
+        idle: false,
+        locked: false,
+        event_txs,
+    };
+    hints.update(
+        Some(proxy.get(SESSION_INTERFACE, "IdleHint")?),
+        Some(proxy.get(SESSION_INTERFACE, "LockedHint")?),
+    );
+
+    proxy.match_signal(
+        move |changed: PropertiesPropertiesChanged, _: &Connection, _: &Message| {
+            if changed.interface_name == SESSION_INTERFACE {
+                let properties = &changed.changed_properties;
+                hints.update(
+                    prop_cast::<bool>(properties, "IdleHint").copied(),
+                    prop_cast::<bool>(properties, "LockedHint").copied(),
+                );
+            }
+            true
+        },
+    )?;
+
+    loop {
+        connection.process(Duration::from_secs(60))?;
+    }
+}
@@ -0,0 +1,30 @@
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// Empty directory for files used by a test, it is removed once the test is over.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Name must be unique among tests, as they run in parallel within the same process.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("wluma-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}